@@ -0,0 +1,47 @@
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+    pub fn min(&self) -> Vec3 { self.min.clone() }
+    pub fn max(&self) -> Vec3 { self.max.clone() }
+    pub fn center(&self) -> Vec3 { (self.min() + self.max()) * 0.5 }
+
+    pub fn axis(v: &Vec3, a: usize) -> f64 {
+        match a {
+            0 => v.x,
+            1 => v.y,
+            _ => v.z,
+        }
+    }
+
+    // slab test, t_min/t_max narrowed axis by axis
+    pub fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        let mut t_min:f64 = t_min;
+        let mut t_max:f64 = t_max;
+        for a in 0..3 {
+            let inv:f64 = 1.0 / Aabb::axis(&r.dir, a);
+            let mut t0:f64 = (Aabb::axis(&self.min, a) - Aabb::axis(&r.org, a)) * inv;
+            let mut t1:f64 = (Aabb::axis(&self.max, a) - Aabb::axis(&r.org, a)) * inv;
+            if inv < 0.0 { std::mem::swap(&mut t0, &mut t1); }
+            if t0 > t_min { t_min = t0; }
+            if t1 < t_max { t_max = t1; }
+            if t_max <= t_min { return false; }
+        }
+        true
+    }
+
+    pub fn surrounding(b0: &Aabb, b1: &Aabb) -> Aabb {
+        let small:Vec3 = Vec3::new(b0.min.x.min(b1.min.x), b0.min.y.min(b1.min.y), b0.min.z.min(b1.min.z));
+        let big:Vec3 = Vec3::new(b0.max.x.max(b1.max.x), b0.max.y.max(b1.max.y), b0.max.z.max(b1.max.z));
+        Aabb::new(small, big)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,146 @@
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::color::Color;
+use crate::shapes::Hitrec;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pass {
+    Depth,
+    Normal,
+    Albedo,
+    Position,
+    Material,
+    Object,
+}
+
+impl Pass {
+    pub const NAMES:[&'static str; 6] = ["depth", "normal", "albedo", "position", "material", "object"];
+    pub const ALL:[Pass; 6] = [Pass::Depth, Pass::Normal, Pass::Albedo, Pass::Position, Pass::Material, Pass::Object];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "depth" => Some(Pass::Depth),
+            "normal" => Some(Pass::Normal),
+            "albedo" => Some(Pass::Albedo),
+            "position" => Some(Pass::Position),
+            "material" => Some(Pass::Material),
+            "object" => Some(Pass::Object),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[*self as usize]
+    }
+
+    // EXR channel names
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Pass::Depth => &["Z"],
+            Pass::Normal | Pass::Position => &["X", "Y", "Z"],
+            Pass::Albedo => &["R", "G", "B"],
+            Pass::Material | Pass::Object => &["id"],
+        }
+    }
+}
+
+// what the camera rays first hit, per pixel: depth, normal, albedo and position
+// are averaged over the samples that hit something, the ids come from the first
+// of them; pixels where every sample missed stay 0
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aovs {
+    pub width: i32,
+    pub height: i32,
+    pub hits: Vec<i32>,
+    pub depth: Vec<f64>,
+    pub normal: Vec<Vec3>,
+    pub albedo: Vec<Color>,
+    pub position: Vec<Vec3>,
+    pub material: Vec<u32>,
+    pub object: Vec<u32>,
+}
+
+impl Aovs {
+    pub fn new(width: i32, height: i32) -> Self {
+        let len:usize = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            hits: vec![0; len],
+            depth: vec![0.0; len],
+            normal: vec![Vec3::zero(); len],
+            albedo: vec![Color::zero(); len],
+            position: vec![Vec3::zero(); len],
+            material: vec![0; len],
+            object: vec![0; len],
+        }
+    }
+
+    // one camera ray of pixel k and its first hit
+    pub fn add(&mut self, k: usize, r: &Ray, rec: Option<&Hitrec>) {
+        let rec:&Hitrec = match rec {
+            Some(rec) => rec,
+            None => return,
+        };
+        if self.hits[k] == 0 {
+            self.material[k] = rec.mtl;
+            self.object[k] = rec.obj;
+        }
+        self.hits[k] += 1;
+        self.depth[k] += rec.t * r.diraction().length();
+        self.normal[k] += rec.nf();
+        self.albedo[k] += rec.mat.albedo(rec);
+        self.position[k] += rec.p();
+    }
+
+    // adds `other`, whose top row is row `top` of these buffers; rows outside are dropped
+    pub fn merge(&mut self, other: &Aovs, top: i32) {
+        let wid:usize = self.width as usize;
+        for b in 0..other.height {
+            let row:i32 = top + b;
+            if row < 0 || row >= self.height { continue; }
+            for a in 0..wid {
+                let (k, o):(usize, usize) = (row as usize * wid + a, b as usize * wid + a);
+                if other.hits[o] == 0 { continue; }
+                if self.hits[k] == 0 {
+                    self.material[k] = other.material[o];
+                    self.object[k] = other.object[o];
+                }
+                self.hits[k] += other.hits[o];
+                self.depth[k] += other.depth[o];
+                self.normal[k] += other.normal[o].clone();
+                self.albedo[k] += other.albedo[o].clone();
+                self.position[k] += other.position[o].clone();
+            }
+        }
+    }
+
+    // the pass as one plane per channel, in the order of pass.channels()
+    pub fn planes(&self, pass: Pass) -> Vec<Vec<f32>> {
+        let len:usize = self.hits.len();
+        let mut planes:Vec<Vec<f32>> = vec![vec![0.0; len]; pass.channels().len()];
+        for k in 0..len {
+            if self.hits[k] == 0 { continue; }
+            let n:f64 = self.hits[k] as f64;
+            let vals:Vec<f64> = match pass {
+                Pass::Depth => vec![self.depth[k] / n],
+                Pass::Normal => {
+                    let v:Vec3 = self.normal[k].clone();
+                    if v.length() > 0.0 { let u:Vec3 = v.unit(); vec![u.x, u.y, u.z] } else { vec![0.0; 3] }
+                }
+                Pass::Albedo => {
+                    let c:Color = self.albedo[k].clone() / n;
+                    vec![c.x, c.y, c.z]
+                }
+                Pass::Position => {
+                    let p:Vec3 = self.position[k].clone() / n;
+                    vec![p.x, p.y, p.z]
+                }
+                Pass::Material => vec![self.material[k] as f64],
+                Pass::Object => vec![self.object[k] as f64],
+            };
+            for (plane, v) in planes.iter_mut().zip(vals) { plane[k] = v as f32; }
+        }
+        planes
+    }
+}
\ No newline at end of file
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use crate::tools;
+use crate::stats;
+use crate::sampler::Sampler;
+use crate::shapes::Hitrec;
+use crate::shapes::Hittable;
+use crate::shapes::Hitlist;
+
+#[derive(Debug)]
+pub struct BvhNode {
+    pub left: Arc<dyn Hittable>,
+    pub right: Arc<dyn Hittable>,
+    pub bbox: Aabb,
+}
+
+impl BvhNode {
+    pub fn new(list: Hitlist) -> Self {
+        Self::build(list.shapes)
+    }
+
+    // split on the longest axis of the centroid bounds, at the median
+    pub fn build(mut shapes: Vec<Arc<dyn Hittable>>) -> Self {
+        if shapes.is_empty() { panic!("bvh over an empty list"); }
+        let boxes:Vec<Aabb> = shapes.iter()
+            .map(|s| s.bounding_box().expect("bvh over a shape without a bounding box"))
+            .collect();
+        let mut cbox:Aabb = Aabb::new(boxes[0].center(), boxes[0].center());
+        for b in &boxes {
+            cbox = Aabb::surrounding(&cbox, &Aabb::new(b.center(), b.center()));
+        }
+        let ext = cbox.max() - cbox.min();
+        let axis:usize = if ext.x > ext.y && ext.x > ext.z { 0 } else if ext.y > ext.z { 1 } else { 2 };
+
+        let mut keyed:Vec<(f64, Arc<dyn Hittable>)> = boxes.iter()
+            .map(|b| Aabb::axis(&b.center(), axis))
+            .zip(shapes.drain(..))
+            .collect();
+        keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let mut sorted:Vec<Arc<dyn Hittable>> = keyed.into_iter().map(|(_, s)| s).collect();
+
+        let (left, right):(Arc<dyn Hittable>, Arc<dyn Hittable>) = match sorted.len() {
+            1 => (sorted[0].clone(), sorted[0].clone()),
+            2 => (sorted[0].clone(), sorted[1].clone()),
+            n => {
+                let rest:Vec<Arc<dyn Hittable>> = sorted.split_off(n / 2);
+                (Arc::new(BvhNode::build(sorted)), Arc::new(BvhNode::build(rest)))
+            }
+        };
+        let bbox:Aabb = Aabb::surrounding(&left.bounding_box().unwrap(), &right.bounding_box().unwrap());
+        Self { left, right, bbox }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<Hitrec> {
+        stats::visit();
+        if !self.bbox.hit(&r, t_min, t_max) { return None; }
+        match self.left.hit(r.clone(), t_min, t_max) {
+            Some(lrec) => {
+                let closest:f64 = lrec.t;
+                match self.right.hit(r, t_min, closest) {
+                    Some(rrec) => Some(rrec),
+                    None => Some(lrec),
+                }
+            }
+            None => self.right.hit(r, t_min, t_max),
+        }
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox.clone())
+    }
+    // each child half the time, so a mesh light can be sampled through its tree
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        if !self.bbox.hit(&Ray::new(o.clone(), v.clone(), 0.0), 0.001, tools::INF) { return 0.0; }
+        0.5 * self.left.pdf_value(o, v) + 0.5 * self.right.pdf_value(o, v)
+    }
+    fn random(&self, o: &Vec3, smp: &mut dyn Sampler) -> Vec3 {
+        if smp.randf(0.0, 1.0) < 0.5 { self.left.random(o, smp) } else { self.right.random(o, smp) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::Rng;
+    use crate::color::Color;
+    use crate::material::Lamber;
+    use crate::material::Isotropic;
+    use crate::medium::ConstantMedium;
+    use crate::shapes::*;
+    use crate::transform::Mat4;
+    use crate::transform::Transform;
+
+    fn rand_vec(rng: &mut Rng, low: f64, high: f64) -> Vec3 {
+        Vec3::new(rng.randf(low, high), rng.randf(low, high), rng.randf(low, high))
+    }
+
+    // one of each kind of shape, centered on `c`
+    fn shape(kind: usize, c: Vec3, rng: &mut Rng) -> Arc<dyn Hittable> {
+        let mat:Lamber = Lamber::new(Color::new(0.5, 0.5, 0.5));
+        let s:f64 = rng.randf(0.3, 1.5);
+        match kind {
+            0 => Arc::new(Sphere::new(c, s, mat)),
+            1 => Arc::new(MovingSphere::new(c.clone(), c + rand_vec(rng, -1.0, 1.0), 0.0, 1.0, s, mat)),
+            2 => Arc::new(XYRect::new(c.x - s, c.x + s, c.y - s, c.y + s, c.z, mat)),
+            3 => Arc::new(XZRect::new(c.x - s, c.x + s, c.z - s, c.z + s, c.y, mat)),
+            4 => Arc::new(YZRect::new(c.y - s, c.y + s, c.z - s, c.z + s, c.x, mat)),
+            5 => Arc::new(Cuboid::new(c.clone() - Vec3::new(s, s, s), c + Vec3::new(s, s, s), mat)),
+            6 => Arc::new(Triangle::new(c.clone() + rand_vec(rng, -s, s), c.clone() + rand_vec(rng, -s, s), c + rand_vec(rng, -s, s), mat)),
+            7 => Arc::new(Flip::new(Sphere::new(c, s, mat))),
+            8 => Arc::new(ConstantMedium::new(Arc::new(Sphere::new(c, s, mat)), 1.0, Isotropic::new(Color::new(1.0, 1.0, 1.0)), 1)),
+            _ => {
+                let m:Mat4 = Mat4::translate(c) * Mat4::rotate(rand_vec(rng, 0.1, 1.0), rng.randf(0.0, 360.0)) * Mat4::scale(rand_vec(rng, 0.3, 1.5));
+                Arc::new(Transform::new(Arc::new(Cuboid::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0), mat)), m))
+            }
+        }
+    }
+
+    #[test]
+    fn hits_what_the_list_hits() {
+        let mut rng:Rng = Rng::new(7, 0);
+        let mut list:Hitlist = Hitlist::new();
+        for k in 0..300 {
+            let c:Vec3 = rand_vec(&mut rng, -20.0, 20.0);
+            let s:Arc<dyn Hittable> = shape(k % 10, c, &mut rng);
+            list.add(Arc::new(Tagged::new(s, k as u32 + 1, 0)));
+        }
+        // a second tree over a list that holds a tree, like a scene with a mesh
+        let mut nested:Hitlist = Hitlist::new();
+        nested.shapes = list.shapes[..100].to_vec();
+        nested.add(Arc::new(BvhNode::build(list.shapes[100..].to_vec())));
+        let bvh:BvhNode = BvhNode::build(list.shapes.clone());
+        let nested:BvhNode = BvhNode::new(nested);
+
+        let mut hits:usize = 0;
+        for _ in 0..10000 {
+            let o:Vec3 = rand_vec(&mut rng, -25.0, 25.0);
+            let mut r:Ray = Ray::new(o.clone(), rand_vec(&mut rng, -10.0, 10.0) - o, rng.randf(0.0, 1.0));
+            r.fog = rng.randf(0.0, 1.0);
+            let want:Option<Hitrec> = list.hit(r.clone(), 0.001, tools::INF);
+            for tree in &[&bvh, &nested] {
+                let got:Option<Hitrec> = tree.hit(r.clone(), 0.001, tools::INF);
+                match (&want, &got) {
+                    (None, None) => {}
+                    (Some(a), Some(b)) => {
+                        assert_eq!((a.obj, a.t, a.u, a.v, a.front_face), (b.obj, b.t, b.u, b.v, b.front_face));
+                        assert_eq!((&a.p, &a.nf), (&b.p, &b.nf));
+                        assert!(std::ptr::eq(a.mat as *const _ as *const u8, b.mat as *const _ as *const u8));
+                    }
+                    _ => panic!("list and bvh disagree on {:?}", r),
+                }
+            }
+            if want.is_some() { hits += 1; }
+        }
+        // most rays should hit something, or the test says little
+        assert!(hits > 2500, "{} hits", hits);
+    }
+}
@@ -2,6 +2,7 @@ use crate::vec3::Vec3;
 use crate::vec3;
 use crate::ray::Ray;
 use crate::tools;
+use crate::sampler::Sampler;
 
 #[derive(Debug)]
 pub struct Camera {
@@ -13,10 +14,12 @@ pub struct Camera {
     cv: Vec3,
     cw: Vec3,
     lens_radius: f64,
+    time0: f64,     // shutter open/close
+    time1: f64,
 }
 
 impl Camera {
-    pub fn new(lookfrom: Vec3, lookat: Vec3, vup: Vec3, vfov: f64, aspect_ratio: f64, aperture: f64, focus_dist: f64) -> Self {
+    pub fn new(lookfrom: Vec3, lookat: Vec3, vup: Vec3, vfov: f64, aspect_ratio: f64, aperture: f64, focus_dist: f64, time0: f64, time1: f64) -> Self {
         let theta:f64 = tools::dtr(vfov);
         let h:f64 = (theta/2.0).tan();
         let viewport_height:f64 = 2.0 * h;
@@ -41,12 +44,15 @@ impl Camera {
             cv: v.clone(),
             cw: w.clone(),
             lens_radius: len.clone(),
+            time0,
+            time1,
         }
     }
     
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd:Vec3 = vec3::rand_in_unit_disk()*self.lens_radius;
+    pub fn get_ray(&self, s: f64, t: f64, smp: &mut dyn Sampler) -> Ray {
+        let rd:Vec3 = vec3::rand_concentric_disk(smp)*self.lens_radius;
         let offset:Vec3 = self.cu.clone()*rd.x() + self.cv.clone()*rd.y();
-        Ray::new(self.origin.clone() + offset.clone(), self.lower_left_corner.clone() + self.horizontal.clone()*s + self.vertical.clone()*t - self.origin.clone() - offset.clone())
+        let tm:f64 = if self.time1 > self.time0 { smp.randf(self.time0, self.time1) } else { self.time0 };
+        Ray::new(self.origin.clone() + offset.clone(), self.lower_left_corner.clone() + self.horizontal.clone()*s + self.vertical.clone()*t - self.origin.clone() - offset.clone(), tm)
     }
 }
\ No newline at end of file
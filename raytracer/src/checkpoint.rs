@@ -0,0 +1,318 @@
+use std::fs;
+use crate::vec3::Vec3;
+use crate::film::Film;
+use crate::film::Welford;
+use crate::aov::Aovs;
+use crate::sampler::SamplerKind;
+use crate::filter::Filter;
+use crate::filter::FilterKind;
+
+const MAGIC:&[u8; 8] = b"RTCKPT02";
+// bytes per pixel of the film and of the AOVs
+const FILM_RECORD:u64 = 24 + 8 + 4 + 8 + 8;
+const AOV_RECORD:u64 = 4 + 8 + 24 + 24 + 24 + 4 + 4;
+
+// the settings that decide what each sample draws and where it lands; a
+// resumed render must keep them, or two different estimates end up in one film
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setup {
+    pub scene: u64,         // digest of the scene file, 0 for the built-in cover
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    pub samples: i32,       // the stratified sampler cuts its strata from it; a
+                            // resume keeps it, whatever --samples it is given
+    pub filter: Filter,
+    pub maxdeep: i32,
+    pub threshold: f64,
+    pub min_samples: i32,   // only counts when sampling adaptively
+}
+
+impl Setup {
+    // what a resume with `now` would change, None if nothing
+    pub fn differs(&self, now: &Setup) -> Option<String> {
+        let was = |what: &str, a: String, b: String| Some(format!("the checkpoint was rendered with {} {}, not {}", what, a, b));
+        if self.scene != now.scene { return Some("the checkpoint was rendered from another scene file".to_string()); }
+        if self.seed != now.seed { return was("--seed", self.seed.to_string(), now.seed.to_string()); }
+        if self.sampler != now.sampler {
+            return was("--sampler", SamplerKind::NAMES[self.sampler as usize].to_string(), SamplerKind::NAMES[now.sampler as usize].to_string());
+        }
+        if self.filter.kind != now.filter.kind {
+            return was("--filter", FilterKind::NAMES[self.filter.kind as usize].to_string(), FilterKind::NAMES[now.filter.kind as usize].to_string());
+        }
+        if self.filter.radius != now.filter.radius {
+            return was("--filter-radius", self.filter.radius.to_string(), now.filter.radius.to_string());
+        }
+        if self.maxdeep != now.maxdeep { return was("--depth", self.maxdeep.to_string(), now.maxdeep.to_string()); }
+        if self.threshold != now.threshold { return was("--adaptive", self.threshold.to_string(), now.threshold.to_string()); }
+        if self.threshold > 0.0 && self.min_samples != now.min_samples {
+            return was("--min-samples", self.min_samples.to_string(), now.min_samples.to_string());
+        }
+        None
+    }
+}
+
+// FNV-1a, enough to tell scene files apart
+pub fn digest(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+// what a render needs to go on where it stopped: the film with its AOVs, the
+// settings it was drawn with and how many samples per pixel the passes reached
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub setup: Setup,
+    pub upto: i32,
+    pub film: Film,
+}
+
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn i32(&mut self, x: i32) { self.buf.extend_from_slice(&x.to_le_bytes()); }
+    fn u32(&mut self, x: u32) { self.buf.extend_from_slice(&x.to_le_bytes()); }
+    fn u64(&mut self, x: u64) { self.buf.extend_from_slice(&x.to_le_bytes()); }
+    fn f64(&mut self, x: f64) { self.buf.extend_from_slice(&x.to_le_bytes()); }
+    fn vec(&mut self, v: &Vec3) { self.f64(v.x); self.f64(v.y); self.f64(v.z); }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    at: usize,
+}
+
+impl <'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.at + n > self.buf.len() { return Err("the file is cut short".to_string()); }
+        self.at += n;
+        Ok(&self.buf[self.at - n..self.at])
+    }
+    fn i32(&mut self) -> Result<i32, String> {
+        let mut b = [0u8; 4];
+        b.copy_from_slice(self.take(4)?);
+        Ok(i32::from_le_bytes(b))
+    }
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut b = [0u8; 4];
+        b.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(b))
+    }
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+    fn f64(&mut self) -> Result<f64, String> { Ok(f64::from_bits(self.u64()?)) }
+    fn vec(&mut self) -> Result<Vec3, String> { Ok(Vec3::new(self.f64()?, self.f64()?, self.f64()?)) }
+}
+
+// written next to `path` first and then renamed over it, so a crash while
+// saving leaves the previous checkpoint whole
+pub fn save(path: &str, film: &Film, setup: &Setup, upto: i32) -> Result<(), String> {
+    let mut w:Writer = Writer { buf: Vec::new() };
+    w.buf.extend_from_slice(MAGIC);
+    w.i32(film.width);
+    w.i32(film.height);
+    w.u64(setup.scene);
+    w.u64(setup.seed);
+    w.u32(setup.sampler as u32);
+    w.i32(setup.samples);
+    w.u32(setup.filter.kind as u32);
+    w.f64(setup.filter.radius);
+    w.i32(setup.maxdeep);
+    w.f64(setup.threshold);
+    w.i32(setup.min_samples);
+    w.i32(upto);
+    w.u32(film.aovs.is_some() as u32);
+    for k in 0..film.len() {
+        w.vec(&film.sum[k]);
+        w.f64(film.weight[k]);
+        w.i32(film.stats[k].n);
+        w.f64(film.stats[k].mean);
+        w.f64(film.stats[k].m2);
+    }
+    if let Some(aovs) = &film.aovs {
+        for k in 0..film.len() {
+            w.i32(aovs.hits[k]);
+            w.f64(aovs.depth[k]);
+            w.vec(&aovs.normal[k]);
+            w.vec(&aovs.albedo[k]);
+            w.vec(&aovs.position[k]);
+            w.u32(aovs.material[k]);
+            w.u32(aovs.object[k]);
+        }
+    }
+    let tmp:String = format!("{}.tmp", path);
+    fs::write(&tmp, &w.buf).map_err(|e| format!("cannot write `{}`: {}", tmp, e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("cannot write `{}`: {}", path, e))
+}
+
+pub fn load(path: &str) -> Result<Checkpoint, String> {
+    let buf:Vec<u8> = fs::read(path).map_err(|e| format!("cannot read `{}`: {}", path, e))?;
+    let mut r:Reader = Reader { buf: &buf, at: 0 };
+    let bad = |e: String| format!("`{}` is not a checkpoint: {}", path, e);
+    if r.take(8).map_err(bad)? != MAGIC { return Err(bad("wrong header".to_string())); }
+    let read = |r: &mut Reader| -> Result<Checkpoint, String> {
+        let (width, height):(i32, i32) = (r.i32()?, r.i32()?);
+        if width <= 0 || height <= 0 { return Err("bad image size".to_string()); }
+        let scene:u64 = r.u64()?;
+        let seed:u64 = r.u64()?;
+        let sampler:SamplerKind = match SamplerKind::NAMES.get(r.u32()? as usize) {
+            Some(name) => SamplerKind::parse(name).unwrap(),
+            None => return Err("unknown sampler".to_string()),
+        };
+        let samples:i32 = r.i32()?;
+        let kind:FilterKind = match FilterKind::NAMES.get(r.u32()? as usize) {
+            Some(name) => FilterKind::parse(name).unwrap(),
+            None => return Err("unknown filter".to_string()),
+        };
+        let filter:Filter = Filter { kind, radius: r.f64()? };
+        let maxdeep:i32 = r.i32()?;
+        let threshold:f64 = r.f64()?;
+        let min_samples:i32 = r.i32()?;
+        let setup:Setup = Setup { scene, seed, sampler, samples, filter, maxdeep, threshold, min_samples };
+        let upto:i32 = r.i32()?;
+        let has_aovs:bool = r.u32()? != 0;
+        // the size the header claims must be there before it is allocated, a
+        // corrupt one could ask for more memory than there is
+        let record:u64 = if has_aovs { FILM_RECORD + AOV_RECORD } else { FILM_RECORD };
+        let need:Option<u64> = (width as u64).checked_mul(height as u64).and_then(|n| n.checked_mul(record));
+        match need {
+            Some(n) if n <= (r.buf.len() - r.at) as u64 => {}
+            _ => return Err("the file is cut short".to_string()),
+        }
+        let mut film:Film = Film::new(width, height);
+        for k in 0..film.len() {
+            film.sum[k] = r.vec()?;
+            film.weight[k] = r.f64()?;
+            film.stats[k] = Welford { n: r.i32()?, mean: r.f64()?, m2: r.f64()? };
+        }
+        if has_aovs {
+            let mut aovs:Aovs = Aovs::new(width, height);
+            for k in 0..film.len() {
+                aovs.hits[k] = r.i32()?;
+                aovs.depth[k] = r.f64()?;
+                aovs.normal[k] = r.vec()?;
+                aovs.albedo[k] = r.vec()?;
+                aovs.position[k] = r.vec()?;
+                aovs.material[k] = r.u32()?;
+                aovs.object[k] = r.u32()?;
+            }
+            film.aovs = Some(aovs);
+        }
+        if r.at != r.buf.len() { return Err("trailing bytes".to_string()); }
+        Ok(Checkpoint { setup, upto, film })
+    };
+    read(&mut r).map_err(bad)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::tools::temp_path;
+
+    fn setup() -> Setup {
+        Setup {
+            scene: digest(b"camera lookfrom=0,0,5 lookat=0,0,0"),
+            seed: 77,
+            sampler: SamplerKind::Halton,
+            samples: 64,
+            filter: Filter::new(FilterKind::Mitchell),
+            maxdeep: 12,
+            threshold: 0.02,
+            min_samples: 8,
+        }
+    }
+
+    fn film(aovs: bool) -> Film {
+        let mut film:Film = Film::new(4, 3);
+        for k in 0..film.len() {
+            film.sum[k] = Color::new(k as f64 * 0.1, 1.0 / (k as f64 + 1.0), -0.5);
+            film.weight[k] = 1.0 + k as f64;
+            film.stats[k] = Welford { n: k as i32, mean: 0.3 * k as f64, m2: 0.01 };
+        }
+        if aovs {
+            let mut a:Aovs = Aovs::new(4, 3);
+            for k in 0..film.len() {
+                a.hits[k] = k as i32 % 3;
+                a.depth[k] = 2.5 * k as f64;
+                a.normal[k] = Vec3::new(0.0, 1.0, k as f64);
+                a.albedo[k] = Color::new(0.2, 0.4, 0.6);
+                a.position[k] = Vec3::new(-1.0, k as f64, 3.0);
+                a.material[k] = k as u32;
+                a.object[k] = 10 + k as u32;
+            }
+            film.aovs = Some(a);
+        }
+        film
+    }
+
+    #[test]
+    fn round_trips() {
+        for &aovs in &[false, true] {
+            let path:String = temp_path(&format!("round-{}.ckpt", aovs));
+            save(&path, &film(aovs), &setup(), 48).unwrap();
+            let ck:Checkpoint = load(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(ck.setup, setup());
+            assert_eq!(ck.upto, 48);
+            assert_eq!(ck.film, film(aovs));
+        }
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let path:String = temp_path("bad.ckpt");
+        save(&path, &film(true), &setup(), 48).unwrap();
+        let mut bytes:Vec<u8> = fs::read(&path).unwrap();
+        bytes.pop();
+        fs::write(&path, &bytes).unwrap();
+        assert!(load(&path).unwrap_err().ends_with("the file is cut short"));
+        fs::write(&path, b"P3\n4 3\n255\n").unwrap();
+        assert!(load(&path).unwrap_err().ends_with("wrong header"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn refuses_truncated_and_oversized_files() {
+        let path:String = temp_path("short.ckpt");
+        save(&path, &film(true), &setup(), 48).unwrap();
+        let bytes:Vec<u8> = fs::read(&path).unwrap();
+        // cut in the header, in the film and in the AOVs
+        for &len in &[20, 100, bytes.len() - 200] {
+            fs::write(&path, &bytes[..len]).unwrap();
+            assert!(load(&path).unwrap_err().ends_with("the file is cut short"), "cut at {}", len);
+        }
+        // a header asking for a billion by a billion pixels is refused before
+        // anything is allocated
+        let mut big:Vec<u8> = bytes.clone();
+        big[8..12].copy_from_slice(&(1i32 << 30).to_le_bytes());
+        big[12..16].copy_from_slice(&(1i32 << 30).to_le_bytes());
+        fs::write(&path, &big).unwrap();
+        assert!(load(&path).unwrap_err().ends_with("the file is cut short"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tells_what_a_resume_would_change() {
+        assert_eq!(setup().differs(&setup()), None);
+        let mut now:Setup = setup();
+        now.sampler = SamplerKind::Sobol;
+        assert_eq!(setup().differs(&now).unwrap(), "the checkpoint was rendered with --sampler halton, not sobol");
+        let mut now:Setup = setup();
+        now.filter.radius = 1.0;
+        assert!(setup().differs(&now).unwrap().contains("--filter-radius"));
+        let mut now:Setup = setup();
+        now.scene = digest(b"camera lookfrom=0,0,6 lookat=0,0,0");
+        assert!(setup().differs(&now).unwrap().contains("another scene"));
+        // a resume may take more samples than the checkpoint was rendered to
+        let mut now:Setup = setup();
+        now.sampler = SamplerKind::Stratified;
+        now.samples = 128;
+        let mut was:Setup = setup();
+        was.sampler = SamplerKind::Stratified;
+        assert_eq!(was.differs(&now), None);
+    }
+}
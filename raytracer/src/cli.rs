@@ -0,0 +1,430 @@
+use std::path::Path;
+use std::time::Duration;
+use crate::sampler::SamplerKind;
+use crate::color::Look;
+use crate::color::Tonemap;
+use crate::filter::Filter;
+use crate::filter::FilterKind;
+use crate::aov::Pass;
+use crate::denoise::Denoise;
+
+pub const USAGE:&str = "\
+Usage: raytracer [OPTIONS] [SCENE]
+
+Renders SCENE (a scene file, see scenes/) or the built-in cover scene.
+
+Options:
+  -s, --scene <FILE>      scene file, same as the positional SCENE
+  -w, --width <N>         image width in pixels (default: from the scene)
+  -H, --height <N>        image height in pixels (default: width / scene aspect)
+  -n, --samples <N>       samples per pixel, the most with --adaptive (default: from the scene)
+      --adaptive <ERR>    stop a pixel once its relative standard error is below ERR, e.g. 0.02
+      --min-samples <N>   samples every pixel takes before it may stop (default: 16)
+      --heatmap <FILE>    also write the sample count of each pixel as an 8-bit image
+  -d, --depth <N>         maximum ray depth (default: from the scene)
+  -j, --threads <N>       worker threads (default: 8)
+      --seed <N|none>     random seed, `none` for a different image every run (default: 2021,
+                          or the checkpoint's with --resume)
+      --sampler <NAME>    independent, stratified, halton or sobol (default: sobol)
+      --tonemap <NAME>    clamp, reinhard, reinhard-ext, aces or uncharted2 (default: clamp)
+      --exposure <EV>     scale the radiance by 2^EV before tone mapping (default: 0)
+      --white <L>         radiance that maps to white with reinhard-ext (default: 4)
+      --filter <NAME>     pixel filter: box, tent, gaussian, mitchell or lanczos (default: box)
+      --filter-radius <R> filter radius in pixels (default: 0.5, 1, 1.5, 2 and 3 in that order)
+  -o, --output <FILE>     output image, may be repeated; the format follows the
+                          extension (.ppm, .png, .jpg, .bmp, .tga, .tif, or
+                          .exr and .hdr for linear radiance)
+                          (default: image.ppm and output/test.png)
+      --denoise           filter the noise out, guided by the albedo, normal and depth
+      --denoise-strength <S>
+                          how much to smooth, implies --denoise (default: 1)
+      --denoise-iterations <N>
+                          filter passes, each doubling the reach, implies --denoise (default: 5)
+      --denoise-firefly <K>
+                          first clamp pixels over K times the median around them, implies
+                          --denoise (default: off, as it dims highlights too)
+      --aov <PASS=FILE>   also write an AOV of the first camera hits: depth, normal,
+                          albedo, position, material or object (ids); may be repeated
+      --layers <FILE>     also write the image and every AOV as layers of one .exr
+      --time <DURATION>   render for this long, e.g. 90s, 10m or 1h30m, adding passes
+                          over the whole image; --samples, if given, still caps it
+      --pass <N>          samples per pixel in each pass (default: 8 with --time, 16 with
+                          --checkpoint, --resume or --preview, otherwise all in one)
+      --checkpoint <FILE> save the unfinished image to FILE after every pass
+      --resume <FILE>     go on from a checkpoint up to --samples; it is also where
+                          the next checkpoints go. The seed, sampler, filter, depth
+                          and adaptive settings not given are the checkpoint's, and
+                          those given, like the scene and size, must be the same
+      --preview <FILE>    write the image so far after every pass
+      --stats <FILE>      also write the render statistics as JSON
+      --profile           also count intersection tests and bvh node visits, which
+                          slows the render down
+  -h, --help              print this help
+";
+
+pub const DEFAULT_SEED:u64 = 2021;
+pub const DEFAULT_SAMPLER:SamplerKind = SamplerKind::Sobol;
+pub const DEFAULT_FILTER:FilterKind = FilterKind::Box;
+pub const DEFAULT_MIN_SAMPLES:i32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Seed {
+    Default,    // not given
+    Fixed(u64),
+    Random,     // `none`
+}
+
+pub const FORMATS:[&str; 10] = ["ppm", "png", "jpg", "jpeg", "bmp", "tga", "tif", "tiff", "exr", "hdr"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub scene: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub samples: Option<i32>,
+    pub threshold: Option<f64>,
+    pub min_samples: Option<i32>,
+    pub heatmap: Option<String>,
+    pub maxdeep: Option<i32>,
+    pub threads: usize,
+    pub seed: Seed,
+    pub sampler: Option<SamplerKind>,
+    pub look: Look,
+    pub filter: Option<FilterKind>,
+    pub filter_radius: Option<f64>,
+    pub outputs: Vec<String>,
+    pub aovs: Vec<(Pass, String)>,
+    pub layers: Option<String>,
+    pub denoise: Option<Denoise>,
+    pub time: Option<Duration>,
+    pub pass: Option<i32>,
+    pub checkpoint: Option<String>,
+    pub resume: Option<String>,
+    pub preview: Option<String>,
+    pub stats: Option<String>,
+    pub profile: bool,
+    pub help: bool,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self {
+            scene: None,
+            width: None,
+            height: None,
+            samples: None,
+            threshold: None,
+            min_samples: None,
+            heatmap: None,
+            maxdeep: None,
+            threads: 8,
+            seed: Seed::Default,
+            sampler: None,
+            look: Look::new(),
+            filter: None,
+            filter_radius: None,
+            outputs: Vec::new(),
+            aovs: Vec::new(),
+            layers: None,
+            denoise: None,
+            time: None,
+            pass: None,
+            checkpoint: None,
+            resume: None,
+            preview: None,
+            stats: None,
+            profile: false,
+            help: false,
+        }
+    }
+
+    // the pixel filter, what was not given taken from `was` (a checkpoint's)
+    // or else the defaults
+    pub fn filter(&self, was: Option<&Filter>) -> Filter {
+        let mut filter:Filter = match (self.filter, was) {
+            (Some(kind), Some(w)) if kind == w.kind => *w,
+            (Some(kind), _) => Filter::new(kind),
+            (None, Some(w)) => *w,
+            (None, None) => Filter::new(DEFAULT_FILTER),
+        };
+        if let Some(r) = self.filter_radius { filter.radius = r; }
+        filter
+    }
+}
+
+pub fn extension(path: &str) -> Option<String> {
+    Path::new(path).extension().map(|e| e.to_string_lossy().to_lowercase())
+}
+
+fn positive(opt: &str, val: &str) -> Result<i32, String> {
+    match val.parse::<i32>() {
+        Ok(x) if x > 0 => Ok(x),
+        _ => Err(format!("{} expects a positive integer, found `{}`", opt, val)),
+    }
+}
+
+// 90 (seconds), 90s, 10m, 1h30m, 1.5h
+fn duration(opt: &str, val: &str) -> Result<Duration, String> {
+    let bad = || format!("{} expects a duration like 90s, 10m or 1h30m, found `{}`", opt, val);
+    let mut secs:f64 = 0.0;
+    let mut num:String = String::new();
+    for ch in val.chars() {
+        if ch.is_ascii_digit() || ch == '.' {
+            num.push(ch);
+            continue;
+        }
+        let unit:f64 = match ch {
+            'h' => 3600.0,
+            'm' => 60.0,
+            's' => 1.0,
+            _ => return Err(bad()),
+        };
+        secs += num.parse::<f64>().map_err(|_| bad())? * unit;
+        num.clear();
+    }
+    if !num.is_empty() { secs += num.parse::<f64>().map_err(|_| bad())?; }
+    if !(secs > 0.0 && secs.is_finite()) || val.is_empty() { return Err(bad()); }
+    Ok(Duration::from_secs_f64(secs))
+}
+
+fn image_format(path: &str) -> Result<(), String> {
+    match extension(path) {
+        Some(ext) if FORMATS.contains(&ext.as_str()) => Ok(()),
+        _ => Err(format!("cannot tell the image format of `{}` from its extension", path)),
+    }
+}
+
+// args without the program name
+pub fn parse(args: &[String]) -> Result<Options, String> {
+    let mut opts:Options = Options::new();
+    let mut k:usize = 0;
+    while k < args.len() {
+        let arg:&str = &args[k];
+        // --opt=value is accepted as well as --opt value
+        let (name, inline):(&str, Option<&str>) = match arg.find('=') {
+            Some(p) if arg.starts_with("--") => (&arg[..p], Some(&arg[p + 1..])),
+            _ => (arg, None),
+        };
+        let takes_value:bool = match name {
+            "-h" | "--help" => { opts.help = true; false }
+            "--profile" => { opts.profile = true; false }
+            "--denoise" => {
+                if opts.denoise.is_none() { opts.denoise = Some(Denoise::new()); }
+                false
+            }
+            "-s" | "--scene" | "-w" | "--width" | "-H" | "--height" | "-n" | "--samples"
+            | "-d" | "--depth" | "-j" | "--threads" | "--seed" | "--sampler" | "--adaptive" | "--min-samples" | "--heatmap"
+            | "--tonemap" | "--exposure" | "--white" | "--filter" | "--filter-radius" | "--aov" | "--layers"
+            | "--denoise-strength" | "--denoise-iterations" | "--denoise-firefly" | "--time" | "--pass" | "--checkpoint" | "--resume"
+            | "--preview" | "--stats" | "-o" | "--output" => true,
+            _ if name.starts_with('-') && name.len() > 1 => return Err(format!("unknown option `{}`", name)),
+            _ => {
+                if opts.scene.is_some() { return Err(format!("unexpected argument `{}`", arg)); }
+                opts.scene = Some(arg.to_string());
+                false
+            }
+        };
+        if !takes_value {
+            if inline.is_some() { return Err(format!("{} does not take a value", name)); }
+            k += 1;
+            continue;
+        }
+        let val:String = match inline {
+            Some(v) => v.to_string(),
+            None => {
+                k += 1;
+                match args.get(k) {
+                    Some(v) => v.clone(),
+                    None => return Err(format!("{} expects a value", name)),
+                }
+            }
+        };
+        match name {
+            "-s" | "--scene" => {
+                if opts.scene.is_some() { return Err("scene given twice".to_string()); }
+                opts.scene = Some(val);
+            }
+            "-w" | "--width" => opts.width = Some(positive(name, &val)?),
+            "-H" | "--height" => opts.height = Some(positive(name, &val)?),
+            "-n" | "--samples" => opts.samples = Some(positive(name, &val)?),
+            "-d" | "--depth" => opts.maxdeep = Some(positive(name, &val)?),
+            "-j" | "--threads" => opts.threads = positive(name, &val)? as usize,
+            "--seed" => {
+                opts.seed = if val == "none" {
+                    Seed::Random
+                } else {
+                    match val.parse::<u64>() {
+                        Ok(x) => Seed::Fixed(x),
+                        Err(_) => return Err(format!("--seed expects an integer or `none`, found `{}`", val)),
+                    }
+                };
+            }
+            "--adaptive" => {
+                opts.threshold = match val.parse::<f64>() {
+                    Ok(x) if x > 0.0 && x.is_finite() => Some(x),
+                    _ => return Err(format!("--adaptive expects a positive number, found `{}`", val)),
+                };
+            }
+            "--min-samples" => opts.min_samples = Some(positive(name, &val)?),
+            "--heatmap" => {
+                image_format(&val)?;
+                // a color ramp, there is no radiance to keep in a float format
+                if let Some(ext) = extension(&val).filter(|e| e == "exr" || e == "hdr") {
+                    return Err(format!("--heatmap writes an 8-bit image, not .{}", ext));
+                }
+                opts.heatmap = Some(val);
+            }
+            "--sampler" => {
+                opts.sampler = match SamplerKind::parse(&val) {
+                    Some(kind) => Some(kind),
+                    None => return Err(format!("--sampler expects one of {}, found `{}`", SamplerKind::NAMES.join(", "), val)),
+                };
+            }
+            "--filter" => {
+                opts.filter = match FilterKind::parse(&val) {
+                    Some(kind) => Some(kind),
+                    None => return Err(format!("--filter expects one of {}, found `{}`", FilterKind::NAMES.join(", "), val)),
+                };
+            }
+            "--filter-radius" => {
+                opts.filter_radius = match val.parse::<f64>() {
+                    Ok(x) if x >= 0.5 && x <= 8.0 => Some(x),
+                    _ => return Err(format!("--filter-radius expects a number from 0.5 to 8, found `{}`", val)),
+                };
+            }
+            "--denoise-strength" => {
+                let strength:f64 = match val.parse::<f64>() {
+                    Ok(x) if x > 0.0 && x.is_finite() => x,
+                    _ => return Err(format!("--denoise-strength expects a positive number, found `{}`", val)),
+                };
+                opts.denoise.get_or_insert_with(Denoise::new).strength = strength;
+            }
+            "--denoise-iterations" => {
+                opts.denoise.get_or_insert_with(Denoise::new).iterations = positive(name, &val)?;
+            }
+            "--denoise-firefly" => {
+                let k:f64 = match val.parse::<f64>() {
+                    Ok(x) if x >= 1.0 && x.is_finite() => x,
+                    _ => return Err(format!("--denoise-firefly expects a number of at least 1, found `{}`", val)),
+                };
+                opts.denoise.get_or_insert_with(Denoise::new).firefly = Some(k);
+            }
+            "--time" => opts.time = Some(duration(name, &val)?),
+            "--stats" => opts.stats = Some(val),
+            "--pass" => opts.pass = Some(positive(name, &val)?),
+            "--checkpoint" => opts.checkpoint = Some(val),
+            "--resume" => opts.resume = Some(val),
+            "--preview" => {
+                image_format(&val)?;
+                opts.preview = Some(val);
+            }
+            "--aov" => {
+                let (pass, path):(&str, &str) = match val.find('=') {
+                    Some(p) => (&val[..p], &val[p + 1..]),
+                    None => return Err(format!("--aov expects PASS=FILE, found `{}`", val)),
+                };
+                let pass:Pass = match Pass::parse(pass) {
+                    Some(pass) => pass,
+                    None => return Err(format!("--aov expects one of {}, found `{}`", Pass::NAMES.join(", "), pass)),
+                };
+                image_format(path)?;
+                opts.aovs.push((pass, path.to_string()));
+            }
+            "--layers" => {
+                if extension(&val).as_deref() != Some("exr") {
+                    return Err(format!("--layers writes OpenEXR, `{}` does not end in .exr", val));
+                }
+                opts.layers = Some(val);
+            }
+            "--tonemap" => {
+                opts.look.tonemap = match Tonemap::parse(&val) {
+                    Some(kind) => kind,
+                    None => return Err(format!("--tonemap expects one of {}, found `{}`", Tonemap::NAMES.join(", "), val)),
+                };
+            }
+            "--exposure" => {
+                opts.look.exposure = match val.parse::<f64>() {
+                    Ok(x) if x.is_finite() => x,
+                    _ => return Err(format!("--exposure expects a number, found `{}`", val)),
+                };
+            }
+            "--white" => {
+                opts.look.white = match val.parse::<f64>() {
+                    Ok(x) if x > 0.0 && x.is_finite() => x,
+                    _ => return Err(format!("--white expects a positive number, found `{}`", val)),
+                };
+            }
+            _ => {
+                image_format(&val)?;
+                opts.outputs.push(val);
+            }
+        }
+        k += 1;
+    }
+    if opts.outputs.is_empty() {
+        opts.outputs.push("image.ppm".to_string());
+        opts.outputs.push("output/test.png".to_string());
+    }
+    Ok(opts)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(line: &str) -> Result<Options, String> {
+        let args:Vec<String> = line.split_whitespace().map(|s| s.to_string()).collect();
+        parse(&args)
+    }
+
+    #[test]
+    fn defaults_without_arguments() {
+        let opts:Options = parse_str("").unwrap();
+        assert_eq!(opts.outputs, vec!["image.ppm".to_string(), "output/test.png".to_string()]);
+        // a plain average over the pixel, as before filters could be chosen
+        assert_eq!(opts.filter(None), Filter::new(FilterKind::Box));
+        let mut want:Options = Options::new();
+        want.outputs = opts.outputs.clone();
+        assert_eq!(opts, want);
+    }
+
+    #[test]
+    fn reads_values_both_ways() {
+        let opts:Options = parse_str("scenes/cornell.txt -w 300 --height=200 -n 64 --seed none -o a.png --output=b.exr --time 1h30m --denoise-iterations 3 --denoise-firefly 4").unwrap();
+        assert_eq!(opts.scene.as_deref(), Some("scenes/cornell.txt"));
+        assert_eq!((opts.width, opts.height, opts.samples, opts.seed), (Some(300), Some(200), Some(64), Seed::Random));
+        assert_eq!(opts.outputs, vec!["a.png".to_string(), "b.exr".to_string()]);
+        assert_eq!(opts.time, Some(Duration::from_secs(5400)));
+        assert_eq!(opts.denoise.map(|d| (d.iterations, d.firefly)), Some((3, Some(4.0))));
+    }
+
+    #[test]
+    fn takes_the_filter_not_given_from_a_checkpoint() {
+        let was:Filter = Filter { kind: FilterKind::Gaussian, radius: 2.5 };
+        assert_eq!(parse_str("").unwrap().filter(Some(&was)), was);
+        assert_eq!(parse_str("--filter gaussian").unwrap().filter(Some(&was)), was);
+        assert_eq!(parse_str("--filter-radius 1").unwrap().filter(Some(&was)), Filter { kind: FilterKind::Gaussian, radius: 1.0 });
+        assert_eq!(parse_str("--filter tent").unwrap().filter(Some(&was)), Filter::new(FilterKind::Tent));
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        for (line, msg) in &[
+            ("--bogus", "unknown option `--bogus`"),
+            ("-w 0", "-w expects a positive integer, found `0`"),
+            ("--samples", "--samples expects a value"),
+            ("--help=yes", "--help does not take a value"),
+            ("a.txt b.txt", "unexpected argument `b.txt`"),
+            ("-o image.gif", "cannot tell the image format of `image.gif` from its extension"),
+            ("--time 10x", "--time expects a duration like 90s, 10m or 1h30m, found `10x`"),
+            ("--layers out.png", "--layers writes OpenEXR, `out.png` does not end in .exr"),
+            ("--heatmap h.exr", "--heatmap writes an 8-bit image, not .exr"),
+            ("--heatmap=h.HDR", "--heatmap writes an 8-bit image, not .hdr"),
+            ("--denoise-firefly 0.5", "--denoise-firefly expects a number of at least 1, found `0.5`"),
+        ] {
+            assert_eq!(parse_str(line).unwrap_err(), *msg);
+        }
+        assert!(parse_str("--sampler magic").unwrap_err().starts_with("--sampler expects one of"));
+    }
+}
@@ -1,36 +1,106 @@
 use crate::tools::clamp;
 use crate::vec3::Vec3;
-use std::fs::File;
-use std::io::prelude::*;
 
 pub type Color = Vec3;
 
-pub fn out_color(color: Color, samples: i32) -> Color {
-    const MAXC:f64 = 256.0;
-    let mut r:f64 = color.x();
-    let mut g:f64 = color.y();
-    let mut b:f64 = color.z();
-
-    let scale:f64 = 1.0 / samples as f64;
-    r = (r*scale).sqrt();
-    g = (g*scale).sqrt();
-    b = (b*scale).sqrt();
-    r = MAXC*clamp(r, 0.0, 0.999);
-    g = MAXC*clamp(g, 0.0, 0.999);
-    b = MAXC*clamp(b, 0.0, 0.999);
-
-    Color::new(r, g, b)
-}
-
-pub fn write_color(file: &mut File, color: Color, samples: i32) {
-    const MAXC:f64 = 256.0;
-    let mut r:f64 = color.x();
-    let mut g:f64 = color.y();
-    let mut b:f64 = color.z();
-
-    let scale:f64 = 1.0 / samples as f64;
-    r = (r*scale).sqrt();
-    g = (g*scale).sqrt();
-    b = (b*scale).sqrt();
-    file.write(format!("{} {} {}\n", (MAXC*clamp(r, 0.0, 0.999)) as i32, (MAXC*clamp(g, 0.0, 0.999)) as i32, (MAXC*clamp(b, 0.0, 0.999)) as i32).as_bytes()).unwrap();
+pub fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tonemap {
+    Clamp,
+    Reinhard,
+    ReinhardExt,
+    Aces,
+    Uncharted2,
+}
+
+impl Tonemap {
+    pub const NAMES:[&'static str; 5] = ["clamp", "reinhard", "reinhard-ext", "aces", "uncharted2"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "clamp" => Some(Tonemap::Clamp),
+            "reinhard" => Some(Tonemap::Reinhard),
+            "reinhard-ext" => Some(Tonemap::ReinhardExt),
+            "aces" => Some(Tonemap::Aces),
+            "uncharted2" => Some(Tonemap::Uncharted2),
+            _ => None,
+        }
+    }
+}
+
+// Hable's filmic curve
+fn uncharted2(x: f64) -> f64 {
+    const A:f64 = 0.15;
+    const B:f64 = 0.50;
+    const C:f64 = 0.10;
+    const D:f64 = 0.20;
+    const E:f64 = 0.02;
+    const F:f64 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+// Narkowicz's fit of the ACES reference rendering transform
+fn aces(x: f64) -> f64 {
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+pub fn srgb_encode(x: f64) -> f64 {
+    if x <= 0.0031308 { 12.92 * x } else { 1.055 * x.powf(1.0 / 2.4) - 0.055 }
+}
+
+pub fn srgb_decode(x: f64) -> f64 {
+    if x <= 0.04045 { x / 12.92 } else { ((x + 0.055) / 1.055).powf(2.4) }
+}
+
+// linear radiance to display values: exposure, tone curve, then sRGB
+#[derive(Debug, Clone, PartialEq)]
+pub struct Look {
+    pub tonemap: Tonemap,
+    pub exposure: f64,      // in stops
+    pub white: f64,         // the radiance that maps to white, for reinhard-ext
+}
+
+impl Look {
+    pub fn new() -> Self {
+        Self { tonemap: Tonemap::Clamp, exposure: 0.0, white: 4.0 }
+    }
+
+    // exposed linear color in, tone-mapped linear color in [0, 1] out
+    pub fn tonemap(&self, c: Color) -> Color {
+        let c:Color = Color::new(c.x.max(0.0), c.y.max(0.0), c.z.max(0.0)) * 2f64.powf(self.exposure);
+        // the Reinhards work on luminance so the hue is kept
+        let by_lum = |f: &dyn Fn(f64) -> f64| {
+            let l:f64 = luminance(&c);
+            if l <= 0.0 { Color::zero() } else { c.clone() * (f(l) / l) }
+        };
+        let out:Color = match self.tonemap {
+            Tonemap::Clamp => c,
+            Tonemap::Reinhard => by_lum(&|l| l / (1.0 + l)),
+            Tonemap::ReinhardExt => {
+                let w2:f64 = self.white * self.white;
+                by_lum(&|l| l * (1.0 + l / w2) / (1.0 + l))
+            }
+            Tonemap::Aces => Color::new(aces(c.x), aces(c.y), aces(c.z)),
+            Tonemap::Uncharted2 => {
+                const BIAS:f64 = 2.0;
+                let w:f64 = uncharted2(11.2);
+                Color::new(uncharted2(BIAS * c.x) / w, uncharted2(BIAS * c.y) / w, uncharted2(BIAS * c.z) / w)
+            }
+        };
+        Color::new(clamp(out.x, 0.0, 1.0), clamp(out.y, 0.0, 1.0), clamp(out.z, 0.0, 1.0))
+    }
+}
+
+impl Default for Look {
+    fn default() -> Self { Self::new() }
+}
+
+// a pixel's radiance to 8-bit sRGB values
+pub fn out_color(color: Color, look: &Look) -> Color {
+    let c:Color = look.tonemap(color);
+    let enc = |x: f64| (255.0 * srgb_encode(x) + 0.5).floor();
+    Color::new(enc(c.x), enc(c.y), enc(c.z))
 }
\ No newline at end of file
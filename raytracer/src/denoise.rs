@@ -0,0 +1,183 @@
+use crate::vec3::Vec3;
+use crate::color;
+use crate::color::Color;
+use crate::film::Film;
+use crate::aov::Aovs;
+use crate::aov::Pass;
+
+// edge-avoiding a-trous wavelet filter (Dammertz et al.), guided by the
+// albedo, normal and depth AOVs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Denoise {
+    pub strength: f64,      // scales how different two colors may be and still be blended
+    pub iterations: i32,    // each one doubles the reach, 5 covers about 60 pixels
+    pub firefly: Option<f64>,   // clamp pixels this many times brighter than their surroundings
+}
+
+impl Denoise {
+    pub fn new() -> Self { Self { strength: 1.0, iterations: 5, firefly: None } }
+}
+
+impl Default for Denoise {
+    fn default() -> Self { Self::new() }
+}
+
+const KERNEL:[f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+const SIGMA_NORMAL:f64 = 0.3;
+const SIGMA_DEPTH:f64 = 0.05;   // relative
+const SIGMA_ALBEDO:f64 = 0.1;
+const EPS:f64 = 0.01;
+
+fn compress(c: &Color) -> Color {
+    Color::new(c.x / (1.0 + c.x), c.y / (1.0 + c.y), c.z / (1.0 + c.z))
+}
+
+fn dist2(a: &Vec3, b: &Vec3) -> f64 {
+    let d:Vec3 = a.clone() - b.clone();
+    d.clone() * d
+}
+
+// replaces the film's pixels with their filtered estimate; needs the AOVs
+pub fn denoise(film: &mut Film, set: &Denoise) {
+    let aovs:&Aovs = film.aovs.as_ref().expect("the denoiser needs the AOVs");
+    let (w, h):(i32, i32) = (film.width, film.height);
+    let vec_of = |pass: Pass| -> Vec<Vec3> {
+        let p:Vec<Vec<f32>> = aovs.planes(pass);
+        (0..film.len()).map(|k| Vec3::new(p[0][k] as f64, p[1][k] as f64, p[2][k] as f64)).collect()
+    };
+    let albedo:Vec<Color> = vec_of(Pass::Albedo);
+    let normal:Vec<Vec3> = vec_of(Pass::Normal);
+    let depth:Vec<f32> = aovs.planes(Pass::Depth).swap_remove(0);
+    let object:&[u32] = &aovs.object;
+
+    // filter the light arriving at the surface rather than the color, so
+    // textures stay sharp
+    let mut light:Vec<Color> = (0..film.len()).map(|k| {
+        let a:&Color = &albedo[k];
+        let c:Color = film.mean(k);
+        let l:Color = Color::new(c.x / (a.x + EPS), c.y / (a.y + EPS), c.z / (a.z + EPS));
+        // a NaN or infinite pixel would bleed into everything around it
+        if l.x.is_finite() && l.y.is_finite() && l.z.is_finite() { l } else { Color::zero() }
+    }).collect();
+
+    // a lone bright sample would never blend with its neighbours, so on request
+    // it is brought down first. Off by default: a highlight or caustic is
+    // just as much brighter than the surface around it
+    if let Some(k) = set.firefly {
+        clamp_fireflies(&mut light, object, w, h, k);
+    }
+
+    let mut sigma:f64 = 0.25 * set.strength;
+    for it in 0..set.iterations.max(0) {
+        let step:i32 = 1 << it;
+        let keyed:Vec<Color> = light.iter().map(compress).collect();
+        let mut next:Vec<Color> = Vec::with_capacity(light.len());
+        for y in 0..h {
+            for x in 0..w {
+                let k:usize = (y * w + x) as usize;
+                let mut sum:Color = Color::zero();
+                let mut wsum:f64 = 0.0;
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    let qy:i32 = y + (j as i32 - 2) * step;
+                    if qy < 0 || qy >= h { continue; }
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let qx:i32 = x + (i as i32 - 2) * step;
+                        if qx < 0 || qx >= w { continue; }
+                        let q:usize = (qy * w + qx) as usize;
+                        let dz:f64 = (depth[k] - depth[q]).abs() as f64 / (depth[k].max(depth[q]) as f64).max(1e-6);
+                        let e:f64 = dist2(&keyed[k], &keyed[q]) / (sigma * sigma).max(1e-12)
+                            + dist2(&normal[k], &normal[q]) / (SIGMA_NORMAL * SIGMA_NORMAL)
+                            + dz / SIGMA_DEPTH
+                            + dist2(&albedo[k], &albedo[q]) / (SIGMA_ALBEDO * SIGMA_ALBEDO);
+                        let wt:f64 = kx * ky * (-e).exp();
+                        sum += light[q].clone() * wt;
+                        wsum += wt;
+                    }
+                }
+                next.push(if wsum > 0.0 { sum / wsum } else { light[k].clone() });
+            }
+        }
+        light = next;
+        // finer detail was filtered by the earlier passes
+        sigma *= 0.5;
+    }
+
+    let out:Vec<Color> = light.iter().zip(&albedo).map(|(l, a)| {
+        Color::new(l.x * (a.x + EPS), l.y * (a.y + EPS), l.z * (a.z + EPS))
+    }).collect();
+    film.weight = vec![1.0; out.len()];
+    film.sum = out;
+}
+
+// scales pixels more than k times brighter than the median of the same object
+// within two pixels down to that (the pixel filter has already spread a lone
+// sample a little, so the neighbourhood is wider than one pixel)
+fn clamp_fireflies(light: &mut [Color], object: &[u32], w: i32, h: i32, k: f64) {
+    let lum:Vec<f64> = light.iter().map(color::luminance).collect();
+    let mut near:Vec<f64> = Vec::with_capacity(25);
+    for y in 0..h {
+        for x in 0..w {
+            let p:usize = (y * w + x) as usize;
+            near.clear();
+            for qy in (y - 2).max(0)..=(y + 2).min(h - 1) {
+                for qx in (x - 2).max(0)..=(x + 2).min(w - 1) {
+                    let q:usize = (qy * w + qx) as usize;
+                    if object[q] == object[p] { near.push(lum[q]); }
+                }
+            }
+            near.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            let median:f64 = near[near.len() / 2];
+            if lum[p] > k * median && lum[p] > 0.0 {
+                light[p] = light[p].clone() * (k * median / lum[p]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a flat grey wall of one object facing the camera, with `bright` pixels lit up
+    fn wall(bright: &[(i32, i32)]) -> Film {
+        let (w, h):(i32, i32) = (16, 16);
+        let mut film:Film = Film::new(w, h);
+        let mut aovs:Aovs = Aovs::new(w, h);
+        for k in 0..film.len() {
+            aovs.hits[k] = 1;
+            aovs.depth[k] = 1.0;
+            aovs.normal[k] = Vec3::new(0.0, 0.0, 1.0);
+            aovs.albedo[k] = Color::new(0.5, 0.5, 0.5);
+            aovs.object[k] = 1;
+            film.sum[k] = Color::new(0.2, 0.2, 0.2);
+            film.weight[k] = 1.0;
+        }
+        for (x, y) in bright {
+            film.sum[(y * w + x) as usize] = Color::new(5.0, 5.0, 5.0);
+        }
+        film.aovs = Some(aovs);
+        film
+    }
+
+    #[test]
+    fn keeps_a_highlight() {
+        let spot:Vec<(i32, i32)> = vec![(7, 7), (8, 7), (7, 8), (8, 8)];
+        let mut film:Film = wall(&spot);
+        denoise(&mut film, &Denoise::new());
+        for (x, y) in &spot {
+            let c:Color = film.mean((y * film.width + x) as usize);
+            assert!(c.x > 4.5, "({}, {}) went down to {}", x, y, c.x);
+        }
+        assert!((film.mean(0).x - 0.2).abs() < 0.01);
+    }
+
+    #[test]
+    fn clamps_a_lone_firefly_on_request() {
+        let mut film:Film = wall(&[(7, 7)]);
+        denoise(&mut film, &Denoise::new());
+        assert!(film.mean(7 * 16 + 7).x > 4.5);
+        let mut film:Film = wall(&[(7, 7)]);
+        denoise(&mut film, &Denoise { firefly: Some(2.0), ..Denoise::new() });
+        assert!(film.mean(7 * 16 + 7).x < 0.5);
+    }
+}
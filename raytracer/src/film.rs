@@ -0,0 +1,102 @@
+use crate::color::Color;
+use crate::filter::Filter;
+use crate::aov::Aovs;
+
+// running mean and variance of the sample luminance (Welford)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Welford {
+    pub n: i32,
+    pub mean: f64,
+    pub m2: f64,
+}
+
+impl Welford {
+    pub fn add(&mut self, x: f64) {
+        self.n += 1;
+        let d:f64 = x - self.mean;
+        self.mean += d / self.n as f64;
+        self.m2 += d * (x - self.mean);
+    }
+    // standard error of the mean against the mean itself; the floor lets
+    // black pixels converge
+    pub fn converged(&self, threshold: f64) -> bool {
+        if self.n < 2 { return false; }
+        let var:f64 = self.m2 / (self.n - 1) as f64;
+        (var / self.n as f64).sqrt() <= threshold * self.mean.max(0.01)
+    }
+    // the stats of both sets of samples together (Chan et al.)
+    pub fn combine(&self, other: &Welford) -> Welford {
+        let n:i32 = self.n + other.n;
+        if n == 0 { return Welford::default(); }
+        let d:f64 = other.mean - self.mean;
+        let (na, nb):(f64, f64) = (self.n as f64, other.n as f64);
+        Welford {
+            n,
+            mean: self.mean + d * nb / n as f64,
+            m2: self.m2 + other.m2 + d * d * na * nb / n as f64,
+        }
+    }
+}
+
+// linear radiance of the image, row-major from the top row: the filter-weighted
+// sum of the samples splatted on each pixel, the sum of their weights, the
+// luminance stats of the samples each pixel took itself (stats[k].n is their
+// count), and the AOVs when they were asked for
+#[derive(Debug, Clone, PartialEq)]
+pub struct Film {
+    pub width: i32,
+    pub height: i32,
+    pub sum: Vec<Color>,
+    pub weight: Vec<f64>,
+    pub stats: Vec<Welford>,
+    pub aovs: Option<Aovs>,
+}
+
+impl Film {
+    pub fn new(width: i32, height: i32) -> Self {
+        let len:usize = width as usize * height as usize;
+        Self { width, height, sum: vec![Color::zero(); len], weight: vec![0.0; len], stats: vec![Welford::default(); len], aovs: None }
+    }
+    pub fn len(&self) -> usize { self.sum.len() }
+    pub fn is_empty(&self) -> bool { self.sum.is_empty() }
+
+    // a sample at (x, y), in pixels from the top left corner, onto every pixel
+    // the filter reaches
+    pub fn splat(&mut self, x: f64, y: f64, c: &Color, filter: &Filter) {
+        let (px, py):(i32, i32) = (x.floor() as i32, y.floor() as i32);
+        let reach:i32 = filter.reach();
+        for b in (py - reach).max(0)..=(py + reach).min(self.height - 1) {
+            for a in (px - reach).max(0)..=(px + reach).min(self.width - 1) {
+                let w:f64 = filter.eval(x - (a as f64 + 0.5), y - (b as f64 + 0.5));
+                if w == 0.0 { continue; }
+                let k:usize = (b * self.width + a) as usize;
+                self.sum[k] += c.clone() * w;
+                self.weight[k] += w;
+            }
+        }
+    }
+
+    // adds `other`, whose top row is row `top` of this film; rows outside are dropped
+    pub fn merge(&mut self, other: &Film, top: i32) {
+        let wid:usize = self.width as usize;
+        for b in 0..other.height {
+            let row:i32 = top + b;
+            if row < 0 || row >= self.height { continue; }
+            for a in 0..wid {
+                let (k, o):(usize, usize) = (row as usize * wid + a, b as usize * wid + a);
+                self.sum[k] += other.sum[o].clone();
+                self.weight[k] += other.weight[o];
+                self.stats[k] = self.stats[k].combine(&other.stats[o]);
+            }
+        }
+        if let (Some(mine), Some(theirs)) = (&mut self.aovs, &other.aovs) {
+            mine.merge(theirs, top);
+        }
+    }
+
+    // the estimate of pixel k, black where no weight landed
+    pub fn mean(&self, k: usize) -> Color {
+        if self.weight[k] <= 0.0 { return Color::zero(); }
+        self.sum[k].clone() / self.weight[k]
+    }
+}
\ No newline at end of file
@@ -0,0 +1,94 @@
+use crate::tools;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+impl FilterKind {
+    pub const NAMES:[&'static str; 5] = ["box", "tent", "gaussian", "mitchell", "lanczos"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "box" => Some(FilterKind::Box),
+            "tent" => Some(FilterKind::Tent),
+            "gaussian" => Some(FilterKind::Gaussian),
+            "mitchell" => Some(FilterKind::Mitchell),
+            "lanczos" => Some(FilterKind::Lanczos),
+            _ => None,
+        }
+    }
+
+    // in pixels
+    pub fn default_radius(&self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 { return 1.0; }
+    let px:f64 = tools::PI * x;
+    px.sin() / px
+}
+
+// Mitchell and Netravali's cubic with B = C = 1/3, over [-2, 2]
+fn mitchell(x: f64) -> f64 {
+    const B:f64 = 1.0 / 3.0;
+    const C:f64 = 1.0 / 3.0;
+    let x:f64 = x.abs();
+    if x < 1.0 {
+        ((12.0 - 9.0 * B - 6.0 * C) * x * x * x + (-18.0 + 12.0 * B + 6.0 * C) * x * x + (6.0 - 2.0 * B)) / 6.0
+    } else if x < 2.0 {
+        ((-B - 6.0 * C) * x * x * x + (6.0 * B + 30.0 * C) * x * x + (-12.0 * B - 48.0 * C) * x + (8.0 * B + 24.0 * C)) / 6.0
+    } else {
+        0.0
+    }
+}
+
+// pixel reconstruction filter, separable in x and y; Mitchell and Lanczos
+// have negative lobes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: f64,
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind) -> Self { Self { kind, radius: kind.default_radius() } }
+
+    fn eval1(&self, x: f64) -> f64 {
+        let r:f64 = self.radius;
+        if x.abs() >= r && self.kind != FilterKind::Box { return 0.0; }
+        match self.kind {
+            FilterKind::Box => if x.abs() <= r { 1.0 } else { 0.0 },
+            FilterKind::Tent => r - x.abs(),
+            FilterKind::Gaussian => {
+                const SIGMA:f64 = 0.5;
+                let g = |x: f64| (-x * x / (2.0 * SIGMA * SIGMA)).exp();
+                (g(x) - g(r)).max(0.0)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / r),
+            FilterKind::Lanczos => sinc(x) * sinc(x / r),
+        }
+    }
+
+    // weight of a sample (x, y) pixels away from a pixel center
+    pub fn eval(&self, x: f64, y: f64) -> f64 {
+        self.eval1(x) * self.eval1(y)
+    }
+
+    // how many pixels out from its own a sample can land
+    pub fn reach(&self) -> i32 {
+        ((self.radius + 0.5).ceil() as i32 - 1).max(0)
+    }
+}
\ No newline at end of file
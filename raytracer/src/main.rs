@@ -1,7 +1,7 @@
 #![allow(warnings, unused)]
-#[allow(clippy::float_cmp)]
-use image::{ImageBuffer, RgbImage};
+#![allow(clippy::float_cmp)]
 use indicatif::ProgressBar;
+use indicatif::ProgressStyle;
 
 pub mod vec3;
 pub mod ray;
@@ -10,6 +10,25 @@ pub mod shapes;
 pub mod tools;
 pub mod camera;
 pub mod material;
+pub mod render;
+pub mod aabb;
+pub mod bvh;
+pub mod scene;
+pub mod cli;
+pub mod output;
+pub mod transform;
+pub mod obj;
+pub mod texture;
+pub mod perlin;
+pub mod medium;
+pub mod pdf;
+pub mod sampler;
+pub mod film;
+pub mod filter;
+pub mod aov;
+pub mod denoise;
+pub mod checkpoint;
+pub mod stats;
 use vec3::Vec3;
 use ray::Ray;
 use color::Color;
@@ -17,129 +36,245 @@ use shapes::Hitrec;
 use shapes::Sphere;
 use shapes::Hittable;
 use shapes::Hitlist;
+use bvh::BvhNode;
 use camera::Camera;
-use tools::randf;
+use film::Film;
+use aov::Aovs;
+use checkpoint::Checkpoint;
+use checkpoint::Setup;
+use stats::Counters;
+use tools::Rng;
 use std::fs::File;
 use std::io::prelude::*;
+use std::process;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 use material::Neg;
 use material::Material;
 use material::Metal;
 use material::Lamber;
 use material::Dielectric;
+use render::Settings;
+use scene::Scene;
+use cli::Options;
+use cli::Seed;
 
-pub fn ray_color(r : Ray, list: &Hitlist, depth: i32) -> Color {
-    if depth <= 0 { return Color::new(0.0, 0.0, 0.0); }
-    match list.hit(r.clone(), 0.001, tools::INF) {
-        Some(rec) => {
-            let tem:Vec3 = Vec3::new(0.0, 0.0, 0.0);
-            let mut scat:Ray = Ray::new(tem.clone(), tem.clone());
-            let mut att:Color = Color::new(0.0, 0.0, 0.0);
-            if rec.mat.scatter(r.clone(), rec.clone(), &mut att, &mut scat) {
-                return Color::elemul(att.clone(), ray_color(scat.clone(), list, depth-1));
-            }else{
-                return Color::new(0.0, 0.0, 0.0);
-            }
-        }
-        None => {
-            let unit_dir = r.diraction().unit();
-            let t = 0.5 * (unit_dir.y() + 1.0);
-            Color::new(1.0, 1.0, 1.0) * (1.0 - t) + Color::new(0.5, 0.7, 1.0) * t
+fn main() {
+    let args:Vec<String> = std::env::args().skip(1).collect();
+    let opts:Options = match cli::parse(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            process::exit(2);
         }
+    };
+    if opts.help {
+        print!("{}", cli::USAGE);
+        return;
     }
-}
-
-
-fn main() {
-    let mut file = File::create("image.ppm").unwrap();
-
-    const AS_RATIO:f64 = 3.0 / 2.0;
-    const I_WID:i32 = 1200;
-    const I_HIT:i32 = (I_WID as f64 / AS_RATIO) as i32;
-    const SAMPLES:i32 = 700; //500
-    const MAXDEEP:i32 = 80; //50
 
-    let mut img: RgbImage = ImageBuffer::new(I_WID as u32, I_HIT as u32);
-    let bar = ProgressBar::new(I_HIT as u64);
+    // a resumed render keeps the seed it was started with, so the scene and
+    // the samples come out the same
+    let resumed:Option<Checkpoint> = opts.resume.as_ref().map(|path| or_exit(checkpoint::load(path)));
+    // --seed none draws the seed once, everything after follows from it
+    let seed:u64 = match (&resumed, opts.seed) {
+        (Some(ck), Seed::Default) => ck.setup.seed,
+        (Some(ck), Seed::Fixed(s)) if s == ck.setup.seed => s,
+        (Some(ck), _) => or_exit(Err(format!("the checkpoint was rendered with --seed {}, a resume cannot change it", ck.setup.seed))),
+        (None, Seed::Default) => cli::DEFAULT_SEED,
+        (None, Seed::Fixed(s)) => s,
+        (None, Seed::Random) => rand::random(),
+    };
+    let mut rng:Rng = Rng::new(seed, 0);
+    let mut scene:Scene = match &opts.scene {
+        Some(path) => match scene::load(path, &mut rng) {
+            Ok(scene) => scene,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        None => scene::cover(&mut rng),
+    };
+    let aspect:f64 = scene.width as f64 / scene.height as f64;
+    match (opts.width, opts.height) {
+        (Some(w), Some(h)) => { scene.width = w; scene.height = h; }
+        (Some(w), None) => { scene.width = w; scene.height = ((w as f64 / aspect) as i32).max(1); }
+        (None, Some(h)) => { scene.height = h; scene.width = ((h as f64 * aspect) as i32).max(1); }
+        (None, None) => {}
+    }
+    let cam:Camera = scene.camera();
 
-    let mut list:Hitlist = Hitlist::new();
-    let mat_g:Lamber = Lamber::new(Color::new(0.5, 0.5, 0.5)); // 0.5
-    let arc_g = Arc::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, mat_g.clone()));
-    list.add(arc_g);
+    // with a time budget and no --samples the passes go on until the time is up
+    let cap:Option<i32> = match (opts.samples, opts.time) {
+        (Some(n), _) => Some(n),
+        (None, Some(_)) => None,
+        (None, None) => Some(scene.samples),
+    };
+    let keep:Option<&String> = opts.checkpoint.as_ref().or_else(|| opts.resume.as_ref());
+    let progressive:bool = keep.is_some() || opts.preview.is_some();
+    let step:i32 = match (opts.pass, cap) {
+        (Some(n), _) => n,
+        (None, _) if opts.time.is_some() => 8,
+        (None, _) if progressive => 16,
+        (None, Some(n)) => n,
+        (None, None) => 8,
+    };
+    // the stratified sampler cuts its strata from the count expected per
+    // pixel: without a cap that of a pass, and on a resume the checkpoint's,
+    // so the samples added go on with the rounds of strata already begun
+    let samples:i32 = match (&resumed, cap) {
+        (Some(ck), _) => ck.setup.samples,
+        (None, Some(n)) => n,
+        (None, None) => step,
+    };
+    // a resume takes the settings it is not given from the checkpoint, those
+    // it is given must agree with it (see Setup::differs)
+    let was:Option<&Setup> = resumed.as_ref().map(|ck| &ck.setup);
+    let min_samples:i32 = opts.min_samples.or_else(|| was.map(|w| w.min_samples)).unwrap_or(cli::DEFAULT_MIN_SAMPLES);
+    let mut set:Settings = Settings {
+        width: scene.width,
+        height: scene.height,
+        samples,
+        maxdeep: opts.maxdeep.or_else(|| was.map(|w| w.maxdeep)).unwrap_or(scene.maxdeep),
+        threads: opts.threads,
+        seed,
+        sampler: opts.sampler.or_else(|| was.map(|w| w.sampler)).unwrap_or(cli::DEFAULT_SAMPLER),
+        filter: opts.filter(was.map(|w| &w.filter)),
+        aovs: !opts.aovs.is_empty() || opts.layers.is_some() || opts.denoise.is_some(),
+        deadline: None,
+        min_samples: cap.map_or(min_samples, |n| min_samples.min(n)),
+        threshold: opts.threshold.or_else(|| was.map(|w| w.threshold)).unwrap_or(0.0),
+        background: scene.background.clone(),
+        fog: scene.fog,
+    };
 
-    let mut a:i32 = -11;
-    while a < 11 {
-        let mut b:i32 = -11;
-        while b < 11 {
-            let chmat:f64 = randf(0.0, 1.0);
-            let ct:Vec3 = Vec3::new(a as f64 + 0.9 * randf(0.0, 1.0), 0.2, b as f64 + 0.9 * randf(0.0, 1.0));
+    let setup:Setup = Setup {
+        scene: opts.scene.as_ref().map_or(0, |path| checkpoint::digest(&std::fs::read(path).unwrap_or_default())),
+        seed,
+        sampler: set.sampler,
+        samples: set.samples,
+        filter: set.filter,
+        maxdeep: set.maxdeep,
+        threshold: set.threshold,
+        min_samples: set.min_samples,
+    };
+    let (mut film, mut upto):(Film, i32) = match resumed {
+        Some(ck) => {
+            if let Some(e) = ck.setup.differs(&setup) {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+            if ck.film.width != set.width || ck.film.height != set.height {
+                eprintln!("error: the checkpoint is {}x{}, the image {}x{}", ck.film.width, ck.film.height, set.width, set.height);
+                process::exit(1);
+            }
+            if set.aovs && ck.film.aovs.is_none() {
+                eprintln!("error: the checkpoint was rendered without the AOVs");
+                process::exit(1);
+            }
+            (ck.film, ck.upto)
+        }
+        None => {
+            let mut film:Film = Film::new(set.width, set.height);
+            if set.aovs { film.aovs = Some(Aovs::new(set.width, set.height)); }
+            (film, 0)
+        }
+    };
 
-            if (ct.clone() - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if chmat < 0.8 {
-                    let lbc:Color = Color::elemul(Color::randv(), Color::randv());
-                    let mat:Lamber = Lamber::new(lbc);
-                    let arc_s = Arc::new(Sphere::new(ct.clone(), 0.2, mat));
-                    list.add(arc_s);
-                } else if chmat < 0.95 {
-                    let lbc:Color = Color::randvr(0.5, 1.0);
-                    let fuzz:f64 = randf(0.0, 0.5); //0.0 -> 0.5
-                    let mat:Metal = Metal::new(lbc, fuzz);
-                    let arc_s = Arc::new(Sphere::new(ct.clone(), 0.2, mat));
-                    list.add(arc_s);
-                } else {
-                    let mat:Dielectric = Dielectric::new(1.5);
-                    let arc_s = Arc::new(Sphere::new(ct.clone(), 0.2, mat));
-                    list.add(arc_s);
-                }
+    let list:Arc<dyn Hittable> = Arc::new(BvhNode::new(scene.list));
+    let lights:Arc<Hitlist> = Arc::new(scene.lights);
+    let cam:Arc<Camera> = Arc::new(cam);
+    // samples per pixel of the last whole pass, for the clock to show
+    let reached = Arc::new(AtomicI32::new(upto));
+    let stop = Arc::new(AtomicBool::new(false));
+    let (bar, clock):(ProgressBar, Option<thread::JoinHandle<()>>) = match opts.time {
+        Some(budget) => {
+            set.deadline = Some(Instant::now() + budget);
+            (ProgressBar::hidden(), Some(clock(budget, reached.clone(), stop.clone())))
+        }
+        None => {
+            let passes:i64 = ((cap.unwrap_or(upto) - upto).max(0) as i64 + step as i64 - 1) / step as i64;
+            (ProgressBar::new(set.height as u64 * passes as u64), None)
+        }
+    };
+    let mut counts:Counters = Counters::default();
+    stats::profile(opts.profile);
+    let started:Instant = Instant::now();
+    while cap.map_or(true, |n| upto < n) {
+        let target:i32 = cap.map_or(upto + step, |n| (upto + step).min(n));
+        let (whole, pass):(bool, Counters) = render::render(list.clone(), lights.clone(), cam.clone(), &set, &mut film, target, &bar);
+        counts.add(&pass);
+        // a pass the deadline cut short has left rows behind, so the
+        // checkpoint stays at the last whole one
+        if whole {
+            upto = target;
+            reached.store(upto, Ordering::Relaxed);
+            if let Some(path) = keep {
+                or_exit(checkpoint::save(path, &film, &setup, upto));
             }
-            b += 1;
         }
-        a += 1;
+        if let Some(path) = &opts.preview {
+            or_exit(output::save(path, &film, &opts.look));
+        }
+        if !whole { break; }
+    }
+    let seconds:f64 = started.elapsed().as_secs_f64();
+    bar.finish();
+    stop.store(true, Ordering::Relaxed);
+    if let Some(clock) = clock {
+        clock.join().unwrap();
+        let counts = film.stats.iter().map(|s| s.n);
+        eprintln!("{} to {} samples per pixel", counts.clone().min().unwrap_or(0), counts.max().unwrap_or(0));
     }
 
-    let mat_1:Dielectric = Dielectric::new(1.5);
-    let arc_s1 = Arc::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, mat_1));
-    list.add(arc_s1);
+    eprint!("{}", counts.summary(seconds));
+    if let Some(path) = &opts.stats {
+        or_exit(std::fs::write(path, counts.json(seconds)).map_err(|e| format!("cannot write `{}`: {}", path, e)));
+    }
+
+    if let Some(den) = &opts.denoise {
+        denoise::denoise(&mut film, den);
+    }
 
-    let mat_2:Lamber = Lamber::new(Color::new(0.4, 0.2, 0.1));
-    let arc_s2 = Arc::new(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, mat_2));
-    list.add(arc_s2);
+    for path in &opts.outputs {
+        or_exit(output::save(path, &film, &opts.look));
+    }
+    for (pass, path) in &opts.aovs {
+        or_exit(output::save_pass(path, &film, *pass));
+    }
+    if let Some(path) = &opts.layers {
+        or_exit(output::save_layers(path, &film));
+    }
+    if let Some(path) = &opts.heatmap {
+        or_exit(output::save_heatmap(path, &film));
+    }
+}
 
-    let mat_3:Metal = Metal::new(Color::new(0.7, 0.6, 0.5), 0.0);
-    let arc_s3 = Arc::new(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, mat_3));
-    list.add(arc_s3);
-    
-    let lookfrom:Vec3 = Vec3::new(12.0, 2.0, 3.0);
-    let lookat:Vec3 = Vec3::new(0.0, 0.0, 0.0);
-    let vup:Vec3 = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus:f64 = 10.0;
-    let aperture:f64 = 0.1;
-    let cam:Camera = Camera::new(lookfrom.clone(), lookat.clone(), vup.clone(), 20.0, AS_RATIO, aperture, dist_to_focus);
+// progress of a time-budgeted render, until `stop`
+fn clock(budget: Duration, reached: Arc<AtomicI32>, stop: Arc<AtomicBool>) -> thread::JoinHandle<()> {
+    let start:Instant = Instant::now();
+    let bar = ProgressBar::new(budget.as_millis() as u64);
+    bar.set_style(ProgressStyle::default_bar().template("[{elapsed_precise}] {wide_bar} {msg}"));
+    thread::spawn(move || {
+        while !stop.load(Ordering::Relaxed) {
+            let spent:Duration = start.elapsed().min(budget);
+            bar.set_position(spent.as_millis() as u64);
+            bar.set_message(&format!("{}s left, {} spp", (budget - spent).as_secs(), reached.load(Ordering::Relaxed)));
+            thread::sleep(Duration::from_millis(200));
+        }
+        bar.finish();
+    })
+}
 
-    file.write(format!("P3\n{} {}\n255\n", I_WID, I_HIT).as_bytes());
-    let mut j:i32 = I_HIT - 1;
-    while j >= 0 {
-        let mut i:i32 = 0;
-        while i < I_WID {
-            let mut color:Color = Color::new(0.0, 0.0, 0.0);
-            let mut s:i32 = 0;
-            while s < SAMPLES {
-                let u:f64 = (i as f64 + randf(0.0, 1.0)) / ((I_WID - 1) as f64);
-                let v:f64 = (j as f64 + randf(0.0, 1.0)) / ((I_HIT - 1) as f64);
-                let r:Ray = cam.get_ray(u, v);
-                color += ray_color(r, &list, MAXDEEP);
-                s += 1;
-            }
-            let pixel = img.get_pixel_mut(i as u32, (I_HIT - j - 1) as u32);
-            let otc:Color = color::out_color(color.clone(), SAMPLES);
-            *pixel = image::Rgb([otc.x() as u8, otc.y() as u8, otc.z() as u8]);
-            color::write_color(&mut file, color, SAMPLES);
-            i += 1;
+fn or_exit<T>(res: Result<T, String>) -> T {
+    match res {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
         }
-        bar.inc(1);
-        j -= 1;
     }
-
-    img.save("output/test.png").unwrap();
-    bar.finish();
 }
\ No newline at end of file
@@ -4,11 +4,52 @@ use crate::vec3;
 use crate::vec3::Vec3;
 use crate::color::Color;
 use crate::tools;
+use crate::sampler::Sampler;
 use std::cmp::min;
 use std::fmt::Debug;
+use std::sync::Arc;
+use crate::texture::Texture;
+use crate::texture::SolidColor;
+use crate::pdf::Pdf;
+use crate::pdf::CosinePdf;
+use crate::pdf::SpherePdf;
 
-pub trait Material: Debug {
-    fn scatter(&self, r_in: Ray, rec: Hitrec, att: &mut Color, scat: &mut Ray) -> bool;
+// how a hit goes on: along one given ray (mirrors, glass), or in a direction
+// drawn from a pdf and weighted by scattering_pdf, which lets the renderer
+// sample the lights instead
+pub enum Lobe {
+    Specular(Ray),
+    Diffuse(Box<dyn Pdf>),
+}
+
+pub struct Scatrec {
+    pub att: Color,
+    pub lobe: Lobe,
+}
+
+pub trait Material: Debug + Send + Sync {
+    fn scatter(&self, r_in: Ray, rec: Hitrec, smp: &mut dyn Sampler) -> Option<Scatrec>;
+    // density of scattering toward scat.diraction(), only asked for Lobe::Diffuse
+    fn scattering_pdf(&self, r_in: Ray, rec: Hitrec, scat: Ray) -> f64 { 0.0 }
+    fn emitted(&self, r_in: Ray, rec: Hitrec) -> Color { Color::new(0.0, 0.0, 0.0) }
+    // surface color for the albedo AOV
+    fn albedo(&self, rec: &Hitrec) -> Color { Color::new(0.0, 0.0, 0.0) }
+}
+
+// lets shapes share one material, e.g. Sphere<Arc<dyn Material>>
+impl <M: Material + ?Sized> Material for Arc<M> {
+    fn scatter(&self, r_in: Ray, rec: Hitrec, smp: &mut dyn Sampler) -> Option<Scatrec> {
+        (**self).scatter(r_in, rec, smp)
+    }
+    fn scattering_pdf(&self, r_in: Ray, rec: Hitrec, scat: Ray) -> f64 {
+        (**self).scattering_pdf(r_in, rec, scat)
+    }
+    fn emitted(&self, r_in: Ray, rec: Hitrec) -> Color {
+        (**self).emitted(r_in, rec)
+    }
+    fn albedo(&self, rec: &Hitrec) -> Color {
+        (**self).albedo(rec)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -19,47 +60,51 @@ impl Neg {
 }
 
 impl Material for Neg {
-    fn scatter(&self, r_in: Ray, rec: Hitrec, att: &mut Color, scat: &mut Ray) -> bool { false }
+    fn scatter(&self, r_in: Ray, rec: Hitrec, smp: &mut dyn Sampler) -> Option<Scatrec> { None }
 }
 
 #[derive(Debug, Clone)]
 pub struct Lamber {
-    pub lbc: Color,
+    pub lbc: Arc<dyn Texture>,
 }
 
 impl Lamber {
-    pub fn new(lbc: Color) -> Self { Self { lbc } }
-    pub fn color(&self) -> Color { self.lbc.clone() }
+    pub fn new(lbc: Color) -> Self { Self { lbc: Arc::new(SolidColor::new(lbc)) } }
+    pub fn new_tex(lbc: Arc<dyn Texture>) -> Self { Self { lbc } }
+    pub fn color(&self, rec: &Hitrec) -> Color { self.lbc.value(rec.u, rec.v, &rec.p) }
 }
 
 impl Material for Lamber {
-    fn scatter(&self, r_in: Ray, rec: Hitrec, att: &mut Color, scat: &mut Ray) -> bool {
-        let scat_dir:Vec3 = rec.nf() + vec3::rand_uint_vec();
-        scat.copy(Ray::new(rec.p(), scat_dir.clone()));
-        att.copy(self.color());
-        true
+    fn scatter(&self, r_in: Ray, rec: Hitrec, smp: &mut dyn Sampler) -> Option<Scatrec> {
+        Some(Scatrec { att: self.color(&rec), lobe: Lobe::Diffuse(Box::new(CosinePdf::new(&rec.nf()))) })
+    }
+    fn scattering_pdf(&self, r_in: Ray, rec: Hitrec, scat: Ray) -> f64 {
+        vec3::cosine_pdf(rec.nf() * scat.diraction().unit())
     }
+    fn albedo(&self, rec: &Hitrec) -> Color { self.color(rec) }
 }
 
 #[derive(Debug, Clone)]
 pub struct Metal {
-    pub lbc: Color,
+    pub lbc: Arc<dyn Texture>,
     pub fuzz: f64,
 }
 
 impl Metal {
-    pub fn new(lbc: Color, fuzz: f64) -> Self { Self {lbc, fuzz} }
-    pub fn color(&self) -> Color { self.lbc.clone() }
+    pub fn new(lbc: Color, fuzz: f64) -> Self { Self { lbc: Arc::new(SolidColor::new(lbc)), fuzz } }
+    pub fn new_tex(lbc: Arc<dyn Texture>, fuzz: f64) -> Self { Self { lbc, fuzz } }
+    pub fn color(&self, rec: &Hitrec) -> Color { self.lbc.value(rec.u, rec.v, &rec.p) }
     pub fn fuz(&self) -> f64 { self.fuzz.clone() }
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: Ray, rec: Hitrec, att: &mut Color, scat: &mut Ray) -> bool {
+    fn scatter(&self, r_in: Ray, rec: Hitrec, smp: &mut dyn Sampler) -> Option<Scatrec> {
         let rft:Vec3 = Vec3::reflect((r_in.diraction()).unit(), rec.nf());
-        scat.copy(Ray::new(rec.p(), rft.clone() + vec3::rand_in_unit_sphere()*self.fuz()));
-        att.copy(self.color());
-        scat.diraction()*rec.nf() > 0.0
+        let scat:Ray = Ray::new(rec.p(), rft.clone() + vec3::rand_in_unit_sphere(smp)*self.fuz(), r_in.time());
+        if scat.diraction()*rec.nf() <= 0.0 { return None; }
+        Some(Scatrec { att: self.color(&rec), lobe: Lobe::Specular(scat) })
     }
+    fn albedo(&self, rec: &Hitrec) -> Color { self.color(rec) }
 }
 
 pub fn schlick(cosine: f64, ref_idx: f64) -> f64 {
@@ -79,8 +124,8 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: Ray, rec: Hitrec, att: &mut Color, scat: &mut Ray) -> bool {
-        att.copy(Color::new(1.0, 1.0, 1.0));
+    fn scatter(&self, r_in: Ray, rec: Hitrec, smp: &mut dyn Sampler) -> Option<Scatrec> {
+        let mut scat:Ray = Ray::new(Vec3::zero(), Vec3::zero(), r_in.time());
         let rate:f64 = if rec.front_face { 1.0 / self.rdx() } else { self.rdx() };
         let uint_dir:Vec3 = r_in.diraction().unit();
         let tem_cos:f64 = -uint_dir.clone()*rec.nf();
@@ -88,17 +133,59 @@ impl Material for Dielectric {
         let sin_theta:f64 = (1.0 - cos_theta*cos_theta).sqrt();
         if rate*sin_theta > 1.0 {
             let refec:Vec3 = Vec3::reflect(uint_dir.clone(), rec.nf());
-            scat.copy(Ray::new(rec.p(), refec.clone()));
+            scat.copy(Ray::new(rec.p(), refec.clone(), r_in.time()));
         } else {
             let prob:f64 = schlick(cos_theta, rate);
-            if tools::randf(0.0, 1.0) < prob {
+            if smp.randf(0.0, 1.0) < prob {
                 let refec:Vec3 = Vec3::reflect(uint_dir.clone(), rec.nf());
-                scat.copy(Ray::new(rec.p(), refec.clone()));
+                scat.copy(Ray::new(rec.p(), refec.clone(), r_in.time()));
             } else {
                 let refac:Vec3 = Vec3::refract(uint_dir.clone(), rec.nf(), rate);
-                scat.copy(Ray::new(rec.p(), refac.clone()));
+                scat.copy(Ray::new(rec.p(), refac.clone(), r_in.time()));
             }
         }
-        true
+        Some(Scatrec { att: Color::new(1.0, 1.0, 1.0), lobe: Lobe::Specular(scat) })
+    }
+    fn albedo(&self, rec: &Hitrec) -> Color { Color::new(1.0, 1.0, 1.0) }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self { Self { emit } }
+    pub fn color(&self) -> Color { self.emit.clone() }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, r_in: Ray, rec: Hitrec, smp: &mut dyn Sampler) -> Option<Scatrec> { None }
+    fn emitted(&self, r_in: Ray, rec: Hitrec) -> Color { self.color() }
+    fn albedo(&self, rec: &Hitrec) -> Color {
+        let e:Color = self.color();
+        Color::new(e.x.min(1.0), e.y.min(1.0), e.z.min(1.0))
+    }
+}
+
+// scatters the same in every direction, the phase function of fog and smoke
+#[derive(Debug, Clone)]
+pub struct Isotropic {
+    pub lbc: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(lbc: Color) -> Self { Self { lbc: Arc::new(SolidColor::new(lbc)) } }
+    pub fn new_tex(lbc: Arc<dyn Texture>) -> Self { Self { lbc } }
+    pub fn color(&self, rec: &Hitrec) -> Color { self.lbc.value(rec.u, rec.v, &rec.p) }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, r_in: Ray, rec: Hitrec, smp: &mut dyn Sampler) -> Option<Scatrec> {
+        Some(Scatrec { att: self.color(&rec), lobe: Lobe::Diffuse(Box::new(SpherePdf::new())) })
+    }
+    fn scattering_pdf(&self, r_in: Ray, rec: Hitrec, scat: Ray) -> f64 {
+        1.0 / (4.0 * tools::PI)
     }
+    fn albedo(&self, rec: &Hitrec) -> Color { self.color(rec) }
 }
\ No newline at end of file
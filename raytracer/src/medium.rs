@@ -0,0 +1,74 @@
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::aabb::Aabb;
+use crate::shapes::Hitrec;
+use crate::shapes::Hittable;
+use crate::material::Material;
+use crate::tools;
+use crate::stats;
+use crate::stats::Prim;
+
+// fog of constant density inside a closed boundary; the boundary's front_face
+// tells entries from exits, so it may be non-convex and the ray may start inside
+#[derive(Debug)]
+pub struct ConstantMedium <M: Material> {
+    pub boundary: Arc<dyn Hittable>,
+    pub neg_inv_density: f64,
+    pub phase: M,
+    pub seed: u64,      // tells this fog's free paths from another's along the same ray
+}
+
+impl <M: Material> ConstantMedium <M> {
+    pub fn new(boundary: Arc<dyn Hittable>, density: f64, phase: M, seed: u64) -> Self {
+        Self { boundary, neg_inv_density: -1.0 / density, phase, seed }
+    }
+}
+
+impl <M: Material> Hittable for ConstantMedium <M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<Hitrec> {
+        stats::test(Prim::Medium);
+        const EPS:f64 = 0.0001;
+        let len:f64 = r.diraction().length();
+        // free path length in world units, spent over the inside segments in order;
+        // hit() gets no sampler, so the number comes with the ray, mixed with the
+        // seed so that two fogs on one ray scatter independently
+        let h:u64 = tools::mix_seed(self.seed, r.fog.to_bits());
+        let u:f64 = (h >> 11) as f64 / (1u64 << 53) as f64;
+        let mut left:f64 = self.neg_inv_density * u.max(1e-300).ln();
+        let mut cursor:f64 = -tools::INF;
+        let mut entered:Option<f64> = None;
+        loop {
+            let (t, front_face) = match self.boundary.hit(r.clone(), cursor, tools::INF) {
+                Some(rec) => (rec.t, rec.front_face),
+                None => break,
+            };
+            if front_face {
+                entered = Some(t);
+            } else {
+                // no entry seen: the ray comes from inside an open boundary
+                let t0:f64 = entered.unwrap_or(t_min).max(t_min);
+                let t1:f64 = t.min(t_max);
+                if t1 > t0 {
+                    let seg:f64 = (t1 - t0) * len;
+                    if left < seg {
+                        let mut rec:Hitrec = Hitrec::new(&(self.phase));
+                        rec.t = t0 + left / len;
+                        rec.p = r.at(rec.t);
+                        rec.nf = Vec3::new(1.0, 0.0, 0.0);  // arbitrary
+                        rec.front_face = true;
+                        return Some(rec);
+                    }
+                    left -= seg;
+                }
+                entered = None;
+            }
+            if t >= t_max { break; }
+            cursor = t + EPS;
+        }
+        None
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
\ No newline at end of file
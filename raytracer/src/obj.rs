@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::shapes::Hitlist;
+use crate::shapes::Triangle;
+use crate::shapes::Hittable;
+use crate::material::Material;
+use crate::material::Lamber;
+use crate::material::Metal;
+use crate::material::Dielectric;
+use crate::material::DiffuseLight;
+use crate::scene::SceneError;
+use crate::texture::Texture;
+use crate::texture::ImageTexture;
+
+// Wavefront .obj meshes: v, vt, vn and f (polygons are fanned into triangles),
+// with mtllib/usemtl read from .mtl files next to the .obj.
+
+fn err(path: &Path, line: usize, msg: String) -> SceneError {
+    SceneError::new(0, format!("{}:{}: {}", path.display(), line, msg))
+}
+
+fn nums(path: &Path, line: usize, words: &[&str], min: usize) -> Result<Vec<f64>, SceneError> {
+    if words.len() < min {
+        return Err(err(path, line, format!("expected {} numbers, found {}", min, words.len())));
+    }
+    let mut out:Vec<f64> = Vec::with_capacity(words.len());
+    for w in words {
+        match w.parse::<f64>() {
+            Ok(x) if x.is_finite() => out.push(x),
+            _ => return Err(err(path, line, format!("expected a number, found `{}`", w))),
+        }
+    }
+    Ok(out)
+}
+
+// Kd (or the map_Kd image) is the diffuse color; an emitter (Ke), glass
+// (d < 1 or illum 4, 6, 7, 9) or a mirror (illum 3, 5) takes precedence over it
+#[derive(Debug, Clone)]
+struct MtlDesc {
+    kd: Color,
+    ks: Color,
+    ke: Color,
+    ns: f64,
+    ni: f64,
+    d: f64,
+    illum: i32,
+    map_kd: Option<Arc<dyn Texture>>,
+}
+
+impl MtlDesc {
+    fn new() -> Self {
+        Self {
+            kd: Color::new(0.8, 0.8, 0.8),
+            ks: Color::new(0.0, 0.0, 0.0),
+            ke: Color::new(0.0, 0.0, 0.0),
+            ns: 0.0,
+            ni: 1.5,
+            d: 1.0,
+            illum: 2,
+            map_kd: None,
+        }
+    }
+
+    fn emits(&self) -> bool {
+        self.ke.x > 0.0 || self.ke.y > 0.0 || self.ke.z > 0.0
+    }
+
+    fn build(&self) -> Arc<dyn Material> {
+        if self.emits() {
+            return Arc::new(DiffuseLight::new(self.ke.clone()));
+        }
+        if self.d < 1.0 || [4, 6, 7, 9].contains(&self.illum) {
+            return Arc::new(Dielectric::new(self.ni));
+        }
+        if self.illum == 3 || self.illum == 5 {
+            // Ns runs 0..1000, sharper highlights mean less fuzz
+            let fuzz:f64 = (1.0 - self.ns / 1000.0).max(0.0).min(1.0);
+            return Arc::new(Metal::new(self.ks.clone(), fuzz));
+        }
+        match &self.map_kd {
+            Some(tex) => Arc::new(Lamber::new_tex(tex.clone())),
+            None => Arc::new(Lamber::new(self.kd.clone())),
+        }
+    }
+}
+
+// each material with whether it emits
+fn load_mtl(path: &Path, mats: &mut HashMap<String, (Arc<dyn Material>, bool)>) -> Result<(), SceneError> {
+    let text:String = fs::read_to_string(path)
+        .map_err(|e| SceneError::new(0, format!("cannot read `{}`: {}", path.display(), e)))?;
+    let mut cur:Option<(String, MtlDesc)> = None;
+    for (idx, raw) in text.lines().enumerate() {
+        let line:usize = idx + 1;
+        let words:Vec<&str> = raw.split('#').next().unwrap().split_whitespace().collect();
+        if words.is_empty() { continue; }
+        if words[0] == "newmtl" {
+            if let Some((name, desc)) = cur.take() { mats.insert(name, (desc.build(), desc.emits())); }
+            cur = Some((words[1..].join(" "), MtlDesc::new()));
+            continue;
+        }
+        let desc:&mut MtlDesc = match cur.as_mut() {
+            Some((_, desc)) => desc,
+            None => continue,
+        };
+        match words[0] {
+            "Kd" | "Ks" | "Ke" => {
+                let c:Vec<f64> = nums(path, line, &words[1..], 3)?;
+                let c:Color = Color::new(c[0], c[1], c[2]);
+                match words[0] {
+                    "Kd" => desc.kd = c,
+                    "Ks" => desc.ks = c,
+                    _ => desc.ke = c,
+                }
+            }
+            "Ns" => desc.ns = nums(path, line, &words[1..], 1)?[0],
+            "Ni" => desc.ni = nums(path, line, &words[1..], 1)?[0],
+            "d" => desc.d = nums(path, line, &words[1..], 1)?[0],
+            "Tr" => desc.d = 1.0 - nums(path, line, &words[1..], 1)?[0],
+            "illum" => desc.illum = nums(path, line, &words[1..], 1)?[0] as i32,
+            "map_Kd" => {
+                // options such as -s are not supported, the file name comes last
+                let file:&str = match words.last() {
+                    Some(w) if words.len() > 1 => w,
+                    _ => return Err(err(path, line, "map_Kd needs a file name".to_string())),
+                };
+                let dir:&Path = path.parent().unwrap_or_else(|| Path::new(""));
+                match ImageTexture::load(&dir.join(file).to_string_lossy()) {
+                    Ok(img) => desc.map_kd = Some(Arc::new(img)),
+                    Err(e) => return Err(err(path, line, e)),
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, desc)) = cur.take() { mats.insert(name, (desc.build(), desc.emits())); }
+    Ok(())
+}
+
+// obj indices are 1-based, negative ones count back from the last element
+fn index(path: &Path, line: usize, word: &str, len: usize) -> Result<usize, SceneError> {
+    let k:i64 = match word.parse::<i64>() {
+        Ok(k) => k,
+        Err(_) => return Err(err(path, line, format!("bad index `{}`", word))),
+    };
+    let idx:i64 = if k < 0 { len as i64 + k } else { k - 1 };
+    if idx < 0 || idx >= len as i64 {
+        return Err(err(path, line, format!("index {} out of range ({} defined)", k, len)));
+    }
+    Ok(idx as usize)
+}
+
+// the faces, and apart the ones that emit (a Ke in the .mtl) so they can be
+// sampled as lights; `mat` overrides every material from the .mtl files
+pub fn load(path: &Path, mat: Option<Arc<dyn Material>>) -> Result<(Hitlist, Hitlist), SceneError> {
+    let text:String = fs::read_to_string(path)
+        .map_err(|e| SceneError::new(0, format!("cannot read `{}`: {}", path.display(), e)))?;
+    let dir:&Path = path.parent().unwrap_or_else(|| Path::new(""));
+    let default:Arc<dyn Material> = match &mat {
+        Some(m) => m.clone(),
+        None => Arc::new(Lamber::new(Color::new(0.73, 0.73, 0.73))),
+    };
+    let mut mats:HashMap<String, (Arc<dyn Material>, bool)> = HashMap::new();
+    let mut cur:(Arc<dyn Material>, bool) = (default.clone(), false);
+    let mut pos:Vec<Vec3> = Vec::new();
+    let mut uvs:Vec<(f64, f64)> = Vec::new();
+    let mut norms:Vec<Vec3> = Vec::new();
+    let mut list:Hitlist = Hitlist::new();
+    let mut lamps:Hitlist = Hitlist::new();
+
+    for (idx, raw) in text.lines().enumerate() {
+        let line:usize = idx + 1;
+        let words:Vec<&str> = raw.split('#').next().unwrap().split_whitespace().collect();
+        if words.is_empty() { continue; }
+        match words[0] {
+            "v" => {
+                let v:Vec<f64> = nums(path, line, &words[1..], 3)?;
+                pos.push(Vec3::new(v[0], v[1], v[2]));
+            }
+            "vt" => {
+                let v:Vec<f64> = nums(path, line, &words[1..], 1)?;
+                uvs.push((v[0], if v.len() > 1 { v[1] } else { 0.0 }));
+            }
+            "vn" => {
+                let v:Vec<f64> = nums(path, line, &words[1..], 3)?;
+                let n:Vec3 = Vec3::new(v[0], v[1], v[2]);
+                if n.squared_length() == 0.0 {
+                    return Err(err(path, line, "a vertex normal must not be zero".to_string()));
+                }
+                norms.push(n.unit());
+            }
+            "mtllib" => {
+                if mat.is_some() { continue; }
+                for name in &words[1..] {
+                    load_mtl(&dir.join(name), &mut mats)?;
+                }
+            }
+            "usemtl" => {
+                if mat.is_some() { continue; }
+                let name:String = words[1..].join(" ");
+                cur = match mats.get(&name) {
+                    Some(m) => m.clone(),
+                    None => return Err(err(path, line, format!("material `{}` is not in any mtllib", name))),
+                };
+            }
+            "f" => {
+                if words.len() < 4 {
+                    return Err(err(path, line, "a face needs at least 3 vertices".to_string()));
+                }
+                // v, v/vt, v//vn or v/vt/vn
+                let mut verts:Vec<(usize, Option<usize>, Option<usize>)> = Vec::new();
+                for w in &words[1..] {
+                    let parts:Vec<&str> = w.split('/').collect();
+                    let p:usize = index(path, line, parts[0], pos.len())?;
+                    let t:Option<usize> = match parts.get(1) {
+                        Some(s) if !s.is_empty() => Some(index(path, line, s, uvs.len())?),
+                        _ => None,
+                    };
+                    let n:Option<usize> = match parts.get(2) {
+                        Some(s) if !s.is_empty() => Some(index(path, line, s, norms.len())?),
+                        _ => None,
+                    };
+                    verts.push((p, t, n));
+                }
+                for k in 1..verts.len() - 1 {
+                    let tri = [verts[0], verts[k], verts[k + 1]];
+                    let mut shape = Triangle::new(pos[tri[0].0].clone(), pos[tri[1].0].clone(), pos[tri[2].0].clone(), cur.0.clone());
+                    // skip slivers, they have no normal to shade with
+                    let area:f64 = Vec3::cross(shape.p[1].clone() - shape.p[0].clone(), shape.p[2].clone() - shape.p[0].clone()).length();
+                    if area < 1e-12 { continue; }
+                    if let (Some(a), Some(b), Some(c)) = (tri[0].1, tri[1].1, tri[2].1) {
+                        shape.uv = Some([uvs[a], uvs[b], uvs[c]]);
+                    }
+                    if let (Some(a), Some(b), Some(c)) = (tri[0].2, tri[1].2, tri[2].2) {
+                        shape.n = Some([norms[a].clone(), norms[b].clone(), norms[c].clone()]);
+                    }
+                    let shape:Arc<dyn Hittable> = Arc::new(shape);
+                    if cur.1 { lamps.add(shape.clone()); }
+                    list.add(shape);
+                }
+            }
+            _ => {}
+        }
+    }
+    if list.shapes.is_empty() {
+        return Err(SceneError::new(0, format!("`{}` has no faces", path.display())));
+    }
+    Ok((list, lamps))
+}
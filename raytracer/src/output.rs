@@ -0,0 +1,362 @@
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::Path;
+use image::{ImageBuffer, RgbImage};
+use image::codecs::hdr::HdrEncoder;
+use crate::color;
+use crate::color::Color;
+use crate::color::Look;
+use crate::film::Film;
+use crate::aov::Aovs;
+use crate::aov::Pass;
+use crate::tools;
+use crate::cli::extension;
+
+fn write_ppm(path: &str, rgb: &[[u8; 3]], width: i32, height: i32) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(format!("P3\n{} {}\n255\n", width, height).as_bytes())?;
+    for c in rgb {
+        file.write_all(format!("{} {} {}\n", c[0], c[1], c[2]).as_bytes())?;
+    }
+    file.flush()
+}
+
+fn make_dir(path: &str) -> Result<(), String> {
+    if let Some(dir) = Path::new(path).parent() {
+        if !dir.as_os_str().is_empty() {
+            fs::create_dir_all(dir).map_err(|e| format!("cannot create `{}`: {}", dir.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+// 8-bit pixels, row-major from the top row; the format follows the extension
+fn write_rgb8(path: &str, rgb: &[[u8; 3]], width: i32, height: i32) -> Result<(), String> {
+    make_dir(path)?;
+    if extension(path).as_deref() == Some("ppm") {
+        return write_ppm(path, rgb, width, height).map_err(|e| format!("cannot write `{}`: {}", path, e));
+    }
+    let mut img: RgbImage = ImageBuffer::new(width as u32, height as u32);
+    for (k, c) in rgb.iter().enumerate() {
+        *img.get_pixel_mut(k as u32 % width as u32, k as u32 / width as u32) = image::Rgb(*c);
+    }
+    img.save(path).map_err(|e| format!("cannot write `{}`: {}", path, e))
+}
+
+fn put_attr(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    out.extend_from_slice(kind.as_bytes());
+    out.push(0);
+    out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+// single-part scanline OpenEXR, uncompressed 32-bit float; each channel is a
+// name and a plane of width * height values
+fn write_exr(path: &str, width: i32, height: i32, channels: &[(String, Vec<f32>)]) -> std::io::Result<()> {
+    let (w, h):(usize, usize) = (width as usize, height as usize);
+    // channels go in alphabetical order, in the list and in every scanline
+    let mut order:Vec<&(String, Vec<f32>)> = channels.iter().collect();
+    order.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut out:Vec<u8> = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+    let mut chlist:Vec<u8> = Vec::new();
+    for (name, _) in &order {
+        chlist.extend_from_slice(name.as_bytes());
+        chlist.push(0);
+        chlist.extend_from_slice(&2i32.to_le_bytes());   // FLOAT
+        chlist.extend_from_slice(&[0, 0, 0, 0]);         // pLinear, reserved
+        chlist.extend_from_slice(&1i32.to_le_bytes());
+        chlist.extend_from_slice(&1i32.to_le_bytes());
+    }
+    chlist.push(0);
+    let mut window:Vec<u8> = Vec::new();
+    for v in &[0, 0, width - 1, height - 1] { window.extend_from_slice(&v.to_le_bytes()); }
+    put_attr(&mut out, "channels", "chlist", &chlist);
+    put_attr(&mut out, "compression", "compression", &[0]);
+    put_attr(&mut out, "dataWindow", "box2i", &window);
+    put_attr(&mut out, "displayWindow", "box2i", &window);
+    put_attr(&mut out, "lineOrder", "lineOrder", &[0]);
+    put_attr(&mut out, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    put_attr(&mut out, "screenWindowCenter", "v2f", &[0; 8]);
+    put_attr(&mut out, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    out.push(0);
+
+    // offset table, then each scanline as its y, its size and the channel planes
+    let line:usize = order.len() * 4 * w;
+    let first:usize = out.len() + 8 * h;
+    for y in 0..h {
+        out.extend_from_slice(&((first + y * (8 + line)) as u64).to_le_bytes());
+    }
+    for y in 0..h {
+        out.extend_from_slice(&(y as i32).to_le_bytes());
+        out.extend_from_slice(&(line as i32).to_le_bytes());
+        for (_, plane) in &order {
+            for v in &plane[y * w..(y + 1) * w] { out.extend_from_slice(&v.to_le_bytes()); }
+        }
+    }
+    File::create(path)?.write_all(&out)
+}
+
+// Radiance RGBE
+fn write_hdr(path: &str, width: i32, height: i32, rgb: &[Vec<f32>]) -> image::ImageResult<()> {
+    let data:Vec<image::Rgb<f32>> = (0..rgb[0].len()).map(|k| image::Rgb([rgb[0][k], rgb[1][k], rgb[2][k]])).collect();
+    let file = BufWriter::new(File::create(path)?);
+    HdrEncoder::new(file).encode(&data, width as usize, height as usize)
+}
+
+fn beauty(film: &Film) -> Vec<Vec<f32>> {
+    let mut planes:Vec<Vec<f32>> = vec![Vec::with_capacity(film.len()); 3];
+    for k in 0..film.len() {
+        let c:Color = film.mean(k);
+        planes[0].push(c.x() as f32);
+        planes[1].push(c.y() as f32);
+        planes[2].push(c.z() as f32);
+    }
+    planes
+}
+
+// linear float planes to .exr, or to .hdr with one plane repeated as gray
+fn write_float(path: &str, width: i32, height: i32, names: &[&str], planes: Vec<Vec<f32>>) -> Result<(), String> {
+    make_dir(path)?;
+    let done:Result<(), String> = if extension(path).as_deref() == Some("exr") {
+        let channels:Vec<(String, Vec<f32>)> = names.iter().map(|n| n.to_string()).zip(planes).collect();
+        write_exr(path, width, height, &channels).map_err(|e| e.to_string())
+    } else {
+        let rgb:Vec<Vec<f32>> = if planes.len() == 1 { vec![planes[0].clone(); 3] } else { planes };
+        write_hdr(path, width, height, &rgb).map_err(|e| e.to_string())
+    };
+    done.map_err(|e| format!("cannot write `{}`: {}", path, e))
+}
+
+fn is_float(path: &str) -> bool {
+    match extension(path).as_deref() {
+        Some("exr") | Some("hdr") => true,
+        _ => false,
+    }
+}
+
+// .exr and .hdr keep the linear radiance, the rest go through `look` to 8-bit sRGB
+pub fn save(path: &str, film: &Film, look: &Look) -> Result<(), String> {
+    if is_float(path) {
+        return write_float(path, film.width, film.height, &["R", "G", "B"], beauty(film));
+    }
+    let rgb:Vec<[u8; 3]> = (0..film.len()).map(|k| {
+        let otc:Color = color::out_color(film.mean(k), look);
+        [otc.x() as u8, otc.y() as u8, otc.z() as u8]
+    }).collect();
+    write_rgb8(path, &rgb, film.width, film.height)
+}
+
+fn id_color(id: f32) -> [u8; 3] {
+    if id == 0.0 { return [0, 0, 0]; }
+    let h:u64 = tools::mix_seed(0x5eed, id as u64);
+    [(h >> 8) as u8 | 0x20, (h >> 24) as u8 | 0x20, (h >> 40) as u8 | 0x20]
+}
+
+// an AOV as an image: raw floats in .exr and .hdr; otherwise depth as gray
+// (near is bright) and positions as colors, both stretched over the range in
+// the image, normals mapped from [-1, 1], albedo in sRGB and ids as random colors
+pub fn save_pass(path: &str, film: &Film, pass: Pass) -> Result<(), String> {
+    let aovs:&Aovs = film.aovs.as_ref().expect("the AOVs were not recorded");
+    let planes:Vec<Vec<f32>> = aovs.planes(pass);
+    if is_float(path) {
+        return write_float(path, film.width, film.height, pass.channels(), planes);
+    }
+    let hit:Vec<bool> = aovs.hits.iter().map(|&n| n > 0).collect();
+    // the range of each channel over the pixels that hit something
+    let bounds:Vec<(f32, f32)> = planes.iter().map(|plane| {
+        plane.iter().zip(&hit).filter(|(_, &h)| h)
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), (&v, _)| (lo.min(v), hi.max(v)))
+    }).collect();
+    let byte = |x: f64| (255.0 * x.max(0.0).min(1.0) + 0.5) as u8;
+    let rgb:Vec<[u8; 3]> = (0..film.len()).map(|k| {
+        if !hit[k] { return [0, 0, 0]; }
+        let at = |c: usize| planes[c][k] as f64;
+        match pass {
+            Pass::Depth => {
+                let g:u8 = byte(1.0 - (at(0) - bounds[0].0 as f64) / (bounds[0].1 - bounds[0].0).max(1e-6) as f64);
+                [g, g, g]
+            }
+            Pass::Normal => [byte(0.5 * at(0) + 0.5), byte(0.5 * at(1) + 0.5), byte(0.5 * at(2) + 0.5)],
+            Pass::Albedo => [byte(color::srgb_encode(at(0))), byte(color::srgb_encode(at(1))), byte(color::srgb_encode(at(2)))],
+            Pass::Position => {
+                let stretch = |c: usize| byte((at(c) - bounds[c].0 as f64) / (bounds[c].1 - bounds[c].0).max(1e-6) as f64);
+                [stretch(0), stretch(1), stretch(2)]
+            }
+            Pass::Material | Pass::Object => id_color(planes[0][k]),
+        }
+    }).collect();
+    write_rgb8(path, &rgb, film.width, film.height)
+}
+
+// the beauty as R, G, B and every AOV as its own layer, e.g. normal.X
+pub fn save_layers(path: &str, film: &Film) -> Result<(), String> {
+    let aovs:&Aovs = film.aovs.as_ref().expect("the AOVs were not recorded");
+    let mut channels:Vec<(String, Vec<f32>)> = ["R", "G", "B"].iter().map(|n| n.to_string()).zip(beauty(film)).collect();
+    for pass in &Pass::ALL {
+        for (name, plane) in pass.channels().iter().zip(aovs.planes(*pass)) {
+            channels.push((format!("{}.{}", pass.name(), name), plane));
+        }
+    }
+    make_dir(path)?;
+    write_exr(path, film.width, film.height, &channels).map_err(|e| format!("cannot write `{}`: {}", path, e))
+}
+
+// blue at 0 through green and yellow to red at 1
+fn heat(t: f64) -> [u8; 3] {
+    const RAMP:[[f64; 3]; 5] = [[0.0, 0.0, 0.5], [0.0, 0.5, 1.0], [0.2, 0.9, 0.2], [1.0, 0.9, 0.0], [0.9, 0.1, 0.0]];
+    let x:f64 = t.max(0.0).min(1.0) * (RAMP.len() - 1) as f64;
+    let k:usize = (x as usize).min(RAMP.len() - 2);
+    let f:f64 = x - k as f64;
+    let mut out = [0u8; 3];
+    for (c, o) in out.iter_mut().enumerate() {
+        *o = (255.0 * (RAMP[k][c] * (1.0 - f) + RAMP[k + 1][c] * f)) as u8;
+    }
+    out
+}
+
+// sample count per pixel, red for the most any pixel took
+pub fn save_heatmap(path: &str, film: &Film) -> Result<(), String> {
+    let most:i32 = film.stats.iter().map(|s| s.n).max().unwrap_or(0);
+    let rgb:Vec<[u8; 3]> = film.stats.iter().map(|s| heat(s.n as f64 / most.max(1) as f64)).collect();
+    write_rgb8(path, &rgb, film.width, film.height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+    use std::io::BufReader;
+    use image::codecs::hdr::HdrDecoder;
+    use crate::tools::temp_path;
+
+    // a gradient per channel, with values past 1 that 8-bit formats would clip
+    fn planes(w: usize, h: usize) -> Vec<Vec<f32>> {
+        (0..3).map(|c| (0..w * h).map(|k| (k as f32 + 1.0) * 0.37 * (c as f32 + 1.0)).collect()).collect()
+    }
+
+    fn le_i32(b: &[u8], at: usize) -> i32 { i32::from_le_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]]) }
+    fn le_f32(b: &[u8], at: usize) -> f32 { f32::from_le_bytes(b[at..at + 4].try_into().unwrap()) }
+
+    // the attributes every OpenEXR file has to have, with their types and
+    // sizes (OpenEXR File Layout, "Header Attributes"); chlist's size varies
+    const REQUIRED:[(&str, &str, usize); 8] = [
+        ("channels", "chlist", 0),
+        ("compression", "compression", 1),
+        ("dataWindow", "box2i", 16),
+        ("displayWindow", "box2i", 16),
+        ("lineOrder", "lineOrder", 1),
+        ("pixelAspectRatio", "float", 4),
+        ("screenWindowCenter", "v2f", 8),
+        ("screenWindowWidth", "float", 4),
+    ];
+
+    #[test]
+    fn exr_follows_the_spec() {
+        let (w, h):(usize, usize) = (5, 3);
+        let data:Vec<Vec<f32>> = planes(w, h);
+        let names:[&str; 3] = ["R", "B", "A.Z"];
+        let channels:Vec<(String, Vec<f32>)> = names.iter().map(|n| n.to_string()).zip(data.clone()).collect();
+        let path:String = temp_path("test.exr");
+        write_exr(&path, w as i32, h as i32, &channels).unwrap();
+        let b:Vec<u8> = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // magic number 20000630, version 2 with no flags: single-part
+        // scanline, short names, not deep
+        assert_eq!(le_i32(&b, 0), 20000630);
+        assert_eq!(&b[4..8], &[2, 0, 0, 0]);
+
+        // the header: name, type, size and value, until an empty name
+        let cstr = |at: usize| -> (String, usize) {
+            let end:usize = at + b[at..].iter().position(|&c| c == 0).unwrap();
+            (String::from_utf8(b[at..end].to_vec()).unwrap(), end + 1)
+        };
+        let mut attrs:Vec<(String, String, usize, usize)> = Vec::new();
+        let mut at:usize = 8;
+        while b[at] != 0 {
+            let (name, next) = cstr(at);
+            let (kind, next) = cstr(next);
+            let size:usize = le_i32(&b, next) as usize;
+            attrs.push((name, kind, size, next + 4));
+            at = next + 4 + size;
+        }
+        let header_end:usize = at + 1;
+        let value = |name: &str| -> usize {
+            let (_, kind, size, value) = attrs.iter().find(|a| a.0 == name).unwrap_or_else(|| panic!("no {}", name));
+            let (_, want, want_size) = REQUIRED.iter().find(|r| r.0 == name).unwrap();
+            assert_eq!(kind, want, "type of {}", name);
+            if *want_size > 0 { assert_eq!(size, want_size, "size of {}", name); }
+            *value
+        };
+        // NO_COMPRESSION, so one scanline per chunk, in INCREASING_Y order
+        assert_eq!(b[value("compression")], 0);
+        assert_eq!(b[value("lineOrder")], 0);
+        let window:Vec<i32> = vec![0, 0, w as i32 - 1, h as i32 - 1];
+        for name in &["dataWindow", "displayWindow"] {
+            let v:usize = value(name);
+            assert_eq!((0..4).map(|k| le_i32(&b, v + 4 * k)).collect::<Vec<i32>>(), window, "{}", name);
+        }
+        assert!(le_f32(&b, value("pixelAspectRatio")) > 0.0);
+        assert!(le_f32(&b, value("screenWindowWidth")) > 0.0);
+        value("screenWindowCenter");
+
+        // channels: name, pixel type (2 is FLOAT), pLinear and three reserved
+        // zero bytes, x and y sampling; sorted, and ended by an empty name
+        let mut c:usize = value("channels");
+        let mut found:Vec<String> = Vec::new();
+        while b[c] != 0 {
+            let (name, next) = cstr(c);
+            assert_eq!(le_i32(&b, next), 2);
+            assert_eq!(&b[next + 4..next + 8], &[0, 0, 0, 0]);
+            assert_eq!((le_i32(&b, next + 8), le_i32(&b, next + 12)), (1, 1));
+            found.push(name);
+            c = next + 16;
+        }
+        assert_eq!(found, vec!["A.Z", "B", "R"]);
+
+        // the offset table has one entry per chunk, each the absolute
+        // position of its chunk; the chunks follow the table back to back
+        // and each is its y, its size and the channels' values in list order
+        let table:usize = header_end;
+        let line:usize = found.len() * 4 * w;
+        let mut next:usize = table + 8 * h;
+        for y in 0..h {
+            let off:usize = u64::from_le_bytes(b[table + 8 * y..table + 8 * y + 8].try_into().unwrap()) as usize;
+            assert_eq!(off, next, "chunk {}", y);
+            assert_eq!(le_i32(&b, off), window[1] + y as i32);
+            assert_eq!(le_i32(&b, off + 4) as usize, line);
+            for (c, name) in found.iter().enumerate() {
+                let plane:usize = names.iter().position(|n| n == name).unwrap();
+                for x in 0..w {
+                    assert_eq!(le_f32(&b, off + 8 + 4 * (c * w + x)), data[plane][y * w + x]);
+                }
+            }
+            next = off + 8 + line;
+        }
+        assert_eq!(b.len(), next);
+    }
+
+    #[test]
+    fn hdr_reads_back() {
+        let (w, h):(usize, usize) = (4, 6);
+        let data:Vec<Vec<f32>> = planes(w, h);
+        let path:String = temp_path("test.hdr");
+        write_hdr(&path, w as i32, h as i32, &data).unwrap();
+        let dec = HdrDecoder::new(BufReader::new(File::open(&path).unwrap())).unwrap();
+        assert_eq!((dec.metadata().width, dec.metadata().height), (w as u32, h as u32));
+        let px:Vec<image::Rgb<f32>> = dec.read_image_hdr().unwrap();
+        fs::remove_file(&path).unwrap();
+        // RGBE keeps 8 bits of mantissa under the largest channel's exponent
+        for (k, p) in px.iter().enumerate() {
+            let big:f32 = data[2][k];
+            for c in 0..3 {
+                assert!((p[c] - data[c][k]).abs() <= big / 128.0, "pixel {} channel {}: {} for {}", k, c, p[c], data[c][k]);
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use crate::vec3;
+use crate::vec3::Vec3;
+use crate::vec3::Onb;
+use crate::shapes::Hittable;
+use crate::tools::PI;
+use crate::sampler::Sampler;
+
+// a distribution of directions: generate() draws one, value() is its density
+// per unit solid angle
+pub trait Pdf {
+    fn value(&self, dir: &Vec3) -> f64;
+    fn generate(&self, smp: &mut dyn Sampler) -> Vec3;
+}
+
+// cos(theta) / pi around a normal
+pub struct CosinePdf {
+    pub uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(nf: &Vec3) -> Self { Self { uvw: Onb::new(nf) } }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, dir: &Vec3) -> f64 {
+        vec3::cosine_pdf(dir.unit() * self.uvw.w.clone())
+    }
+    fn generate(&self, smp: &mut dyn Sampler) -> Vec3 {
+        self.uvw.to_world(&vec3::rand_cosine_direction(smp))
+    }
+}
+
+// the same in every direction
+pub struct SpherePdf {}
+
+impl SpherePdf {
+    pub fn new() -> Self { Self {} }
+}
+
+impl Pdf for SpherePdf {
+    fn value(&self, dir: &Vec3) -> f64 { 1.0 / (4.0 * PI) }
+    fn generate(&self, smp: &mut dyn Sampler) -> Vec3 { vec3::rand_uint_vec(smp) }
+}
+
+// toward the points of a shape as seen from o, see Hittable::pdf_value
+pub struct HittablePdf <'a> {
+    pub o: Vec3,
+    pub shape: &'a dyn Hittable,
+}
+
+impl <'a> HittablePdf <'a> {
+    pub fn new(o: Vec3, shape: &'a dyn Hittable) -> Self { Self { o, shape } }
+}
+
+impl <'a> Pdf for HittablePdf <'a> {
+    fn value(&self, dir: &Vec3) -> f64 { self.shape.pdf_value(&self.o, dir) }
+    fn generate(&self, smp: &mut dyn Sampler) -> Vec3 { self.shape.random(&self.o, smp) }
+}
+
+// half of each
+pub struct MixturePdf <'a> {
+    pub p: [&'a dyn Pdf; 2],
+}
+
+impl <'a> MixturePdf <'a> {
+    pub fn new(p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> Self { Self { p: [p0, p1] } }
+    // generate(), also telling which of the two drew the direction
+    pub fn pick(&self, smp: &mut dyn Sampler) -> (Vec3, usize) {
+        let k:usize = if smp.randf(0.0, 1.0) < 0.5 { 0 } else { 1 };
+        (self.p[k].generate(smp), k)
+    }
+}
+
+impl <'a> Pdf for MixturePdf <'a> {
+    fn value(&self, dir: &Vec3) -> f64 {
+        0.5 * self.p[0].value(dir) + 0.5 * self.p[1].value(dir)
+    }
+    fn generate(&self, smp: &mut dyn Sampler) -> Vec3 {
+        self.pick(smp).0
+    }
+}
\ No newline at end of file
@@ -0,0 +1,76 @@
+use crate::vec3::Vec3;
+use crate::tools::Rng;
+
+const POINT_COUNT:usize = 256;
+
+// gradient noise with random unit vectors on the lattice
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    ranvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+fn gen_perm(rng: &mut Rng) -> Vec<usize> {
+    let mut p:Vec<usize> = (0..POINT_COUNT).collect();
+    let mut i:usize = POINT_COUNT - 1;
+    while i > 0 {
+        let target:usize = (rng.randf(0.0, (i + 1) as f64) as usize).min(i);
+        p.swap(i, target);
+        i -= 1;
+    }
+    p
+}
+
+impl Perlin {
+    pub fn new(rng: &mut Rng) -> Self {
+        let ranvec:Vec<Vec3> = (0..POINT_COUNT).map(|_| Vec3::randvr(rng, -1.0, 1.0).unit()).collect();
+        Self { ranvec, perm_x: gen_perm(rng), perm_y: gen_perm(rng), perm_z: gen_perm(rng) }
+    }
+
+    // in [-1, 1]
+    pub fn noise(&self, p: &Vec3) -> f64 {
+        let u:f64 = p.x - p.x.floor();
+        let v:f64 = p.y - p.y.floor();
+        let w:f64 = p.z - p.z.floor();
+        let i:i64 = p.x.floor() as i64;
+        let j:i64 = p.y.floor() as i64;
+        let k:i64 = p.z.floor() as i64;
+
+        // hermite smoothing of the weights
+        let uu:f64 = u * u * (3.0 - 2.0 * u);
+        let vv:f64 = v * v * (3.0 - 2.0 * v);
+        let ww:f64 = w * w * (3.0 - 2.0 * w);
+        let mut accum:f64 = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let idx:usize = self.perm_x[((i + di) & 255) as usize]
+                        ^ self.perm_y[((j + dj) & 255) as usize]
+                        ^ self.perm_z[((k + dk) & 255) as usize];
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    let weight:Vec3 = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * (self.ranvec[idx].clone() * weight);
+                }
+            }
+        }
+        accum
+    }
+
+    // sum of `depth` octaves
+    pub fn turb(&self, p: &Vec3, depth: i32) -> f64 {
+        let mut accum:f64 = 0.0;
+        let mut tp:Vec3 = p.clone();
+        let mut weight:f64 = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(&tp);
+            weight *= 0.5;
+            tp *= 2.0;
+        }
+        accum.abs()
+    }
+}
\ No newline at end of file
@@ -4,18 +4,23 @@ use crate::vec3::Vec3;
 pub struct Ray {
     pub org : Vec3,
     pub dir : Vec3,
+    pub tm : f64,     // moment within the camera shutter
+    pub fog : f64,    // uniform number for the free path through fog, see ray_color
 }
 
 impl Ray {
-    pub fn new(org : Vec3, dir : Vec3) -> Self {
-        Ray { org, dir }
+    pub fn new(org : Vec3, dir : Vec3, tm : f64) -> Self {
+        Ray { org, dir, tm, fog: 0.5 }
     }
     pub fn copy(&mut self, other: Self) {
         self.org.copy(other.origin());
         self.dir.copy(other.diraction());
+        self.tm = other.tm;
+        self.fog = other.fog;
     }
     pub fn origin(&self) -> Vec3 { self.org.clone() }
     pub fn diraction(&self) -> Vec3 { self.dir.clone() }
+    pub fn time(&self) -> f64 { self.tm }
     pub fn at(&self, t : f64) -> Vec3 {
         self.org.clone() + self.dir.clone()*t
     }
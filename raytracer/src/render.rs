@@ -0,0 +1,262 @@
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::color;
+use crate::color::Color;
+use crate::shapes::Hitrec;
+use crate::shapes::Hittable;
+use crate::shapes::Hitlist;
+use crate::material::Lobe;
+use crate::pdf::Pdf;
+use crate::pdf::HittablePdf;
+use crate::pdf::MixturePdf;
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::film::Welford;
+use crate::aov::Aovs;
+use crate::filter::Filter;
+use crate::tools;
+use crate::sampler::Sampler;
+use crate::sampler::SamplerKind;
+use crate::stats;
+use crate::stats::Counters;
+use indicatif::ProgressBar;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+use std::sync::mpsc;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub width: i32,
+    pub height: i32,
+    pub samples: i32,       // expected per pixel, the stratified sampler cuts its strata from it
+    pub min_samples: i32,
+    pub threshold: f64,     // relative error to stop a pixel at, 0: always take all samples
+    pub maxdeep: i32,       // a safety cap, paths mostly end by Russian roulette
+    pub threads: usize,
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    pub filter: Filter,
+    pub aovs: bool,         // also record the first hits of the camera rays
+    pub deadline: Option<Instant>,  // rows not started by then are left for later
+    pub background: Background,
+    pub fog: bool,          // the scene has volumes, see Path::fog
+}
+
+// what a ray sees when it leaves the scene
+#[derive(Debug, Clone, PartialEq)]
+pub enum Background {
+    Solid(Color),
+    Gradient(Color, Color),     // looking straight down, straight up
+}
+
+impl Background {
+    pub fn sky() -> Self {
+        Background::Gradient(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0))
+    }
+    pub fn value(&self, r: &Ray) -> Color {
+        match self {
+            Background::Solid(c) => c.clone(),
+            Background::Gradient(bottom, top) => {
+                let unit_dir = r.diraction().unit();
+                let t = 0.5 * (unit_dir.y() + 1.0);
+                bottom.clone() * (1.0 - t) + top.clone() * t
+            }
+        }
+    }
+}
+
+// paths that have bounced this often go on only with a probability that
+// follows their throughput (Russian roulette)
+const RR_MIN_BOUNCES:i32 = 3;
+
+// what ray_color carries down a path
+#[derive(Debug, Clone)]
+pub struct Path {
+    pub depth: i32,     // bounces left before the safety cap
+    pub bounce: i32,
+    pub beta: Color,    // throughput from the camera up to here
+    pub fog: bool,      // each ray draws a number for its free path through fog;
+                        // without fog that dimension is left to the bounces
+}
+
+impl Path {
+    pub fn new(maxdeep: i32, fog: bool) -> Self {
+        Self { depth: maxdeep, bounce: 0, beta: Color::new(1.0, 1.0, 1.0), fog }
+    }
+    // the path after a bounce that weights it by f, and the factor its light is
+    // scaled by to make up for the paths the roulette ends; None if it ends here
+    fn bounce(&self, f: &Color, smp: &mut dyn Sampler, counts: &mut Counters) -> Option<(Path, f64)> {
+        let mut beta:Color = Color::elemul(self.beta.clone(), f.clone());
+        let mut scale:f64 = 1.0;
+        if self.bounce >= RR_MIN_BOUNCES {
+            let survive:f64 = beta.x.max(beta.y).max(beta.z).min(0.95);
+            if survive <= 0.0 || smp.get1d() >= survive {
+                counts.roulette += 1;
+                return None;
+            }
+            scale = 1.0 / survive;
+            beta = beta * scale;
+        }
+        Some((Path { depth: self.depth - 1, bounce: self.bounce + 1, beta, fog: self.fog }, scale))
+    }
+}
+
+// the light arriving along r, and what r hit, which the camera rays keep for the AOVs
+pub fn ray_color<'a>(r : Ray, list: &'a dyn Hittable, lights: &Hitlist, background: &Background, path: &Path, smp: &mut dyn Sampler, counts: &mut Counters) -> (Color, Option<Hitrec<'a>>) {
+    if path.depth <= 0 {
+        counts.depth_limit += 1;
+        return (Color::new(0.0, 0.0, 0.0), None);
+    }
+    let mut r:Ray = r;
+    if path.fog { r.fog = smp.get1d(); }
+    match list.hit(r.clone(), 0.001, tools::INF) {
+        Some(rec) => (shade(&r, &rec, list, lights, background, path, smp, counts), Some(rec)),
+        None => (background.value(&r), None),
+    }
+}
+
+// the light leaving `rec` back along r. Diffuse bounces send half their rays
+// toward `lights`, weighted by the mixed pdf; the rays leaving a hit are
+// counted only if they will be traced
+fn shade(r: &Ray, rec: &Hitrec, list: &dyn Hittable, lights: &Hitlist, background: &Background, path: &Path, smp: &mut dyn Sampler, counts: &mut Counters) -> Color {
+    let emit:Color = rec.mat.emitted(r.clone(), rec.clone());
+    let srec = match rec.mat.scatter(r.clone(), rec.clone(), smp) {
+        Some(srec) => srec,
+        None => return emit,
+    };
+    match srec.lobe {
+        Lobe::Specular(scat) => {
+            let (next, scale):(Path, f64) = match path.bounce(&srec.att, smp, counts) {
+                Some(next) => next,
+                None => return emit,
+            };
+            if next.depth > 0 { counts.specular += 1; }
+            emit + Color::elemul(srec.att, ray_color(scat, list, lights, background, &next, smp, counts).0) * scale
+        }
+        Lobe::Diffuse(mat_pdf) => {
+            let light_pdf = HittablePdf::new(rec.p(), lights);
+            let mix = MixturePdf::new(&light_pdf, &*mat_pdf);
+            let pdf:&dyn Pdf = if lights.shapes.is_empty() { &*mat_pdf } else { &mix };
+            let (dir, toward_light):(Vec3, bool) = if lights.shapes.is_empty() {
+                (mat_pdf.generate(smp), false)
+            } else {
+                let (dir, k) = mix.pick(smp);
+                (dir, k == 0)
+            };
+            let scat:Ray = Ray::new(rec.p(), dir, r.time());
+            let pdf_val:f64 = pdf.value(&scat.diraction());
+            if pdf_val <= 0.0 { return emit; }
+            let weight:f64 = rec.mat.scattering_pdf(r.clone(), rec.clone(), scat.clone()) / pdf_val;
+            if weight <= 0.0 { return emit; }
+            let (next, scale):(Path, f64) = match path.bounce(&(srec.att.clone() * weight), smp, counts) {
+                Some(next) => next,
+                None => return emit,
+            };
+            if next.depth > 0 {
+                if toward_light { counts.light += 1 } else { counts.diffuse += 1 }
+            }
+            emit + Color::elemul(srec.att, ray_color(scat, list, lights, background, &next, smp, counts).0) * (weight * scale)
+        }
+    }
+}
+
+// one scanline, row 0 is the top of the image, splatted onto a band of the
+// rows its filter reaches; the band starts `filter.reach()` rows above `row`.
+// Each pixel goes on from the `prev` samples it has to `upto`, and its stats
+// in the band are those of the new samples only
+fn render_row(list: &dyn Hittable, lights: &Hitlist, cam: &Camera, set: &Settings, row: i32, prev: &[Welford], upto: i32, smp: &mut dyn Sampler, counts: &mut Counters) -> Film {
+    let j:i32 = set.height - 1 - row;
+    let reach:i32 = set.filter.reach();
+    let mut band:Film = Film::new(set.width, 2 * reach + 1);
+    if set.aovs { band.aovs = Some(Aovs::new(set.width, 2 * reach + 1)); }
+    let adaptive:bool = set.threshold > 0.0;
+    let mut i:i32 = 0;
+    while i < set.width {
+        let old:&Welford = &prev[i as usize];
+        let mut stats:Welford = Welford::default();
+        let mut s:i32 = old.n;
+        if adaptive && s >= set.min_samples && old.converged(set.threshold) { s = upto; }
+        let pixel:u64 = row as u64 * set.width as u64 + i as u64;
+        while s < upto {
+            smp.start(pixel, s as u64);
+            let (du, dv) = smp.get2d();
+            let u:f64 = (i as f64 + du) / ((set.width - 1) as f64);
+            let v:f64 = (j as f64 + dv) / ((set.height - 1) as f64);
+            let r:Ray = cam.get_ray(u, v, smp);
+            counts.camera += 1;
+            let (c, first):(Color, Option<Hitrec>) = ray_color(r.clone(), list, lights, &set.background, &Path::new(set.maxdeep, set.fog), smp, counts);
+            if let Some(aovs) = &mut band.aovs {
+                aovs.add((reach * set.width + i) as usize, &r, first.as_ref());
+            }
+            s += 1;
+            stats.add(color::luminance(&c));
+            // v grows upwards, the film's rows downwards
+            band.splat(i as f64 + du, reach as f64 + 1.0 - dv, &c, &set.filter);
+            if adaptive && s >= set.min_samples && old.combine(&stats).converged(set.threshold) { break; }
+        }
+        band.stats[(reach * set.width + i) as usize] = stats;
+        i += 1;
+    }
+    band
+}
+
+// adds samples to `film` until every pixel has `upto` of them (or, sampling
+// adaptively, has converged); false if the deadline stopped it first, and what
+// the workers counted. Rows are handed out to the workers one at a time;
+// every sample draws from the sampler's (seed, pixel, sample) point and the
+// bands are added in row order, so the image does not depend on the thread count
+pub fn render(list: Arc<dyn Hittable>, lights: Arc<Hitlist>, cam: Arc<Camera>, set: &Settings, film: &mut Film, upto: i32, bar: &ProgressBar) -> (bool, Counters) {
+    let next = Arc::new(AtomicUsize::new(0));
+    let prev:Arc<Vec<Welford>> = Arc::new(film.stats.clone());
+    let (tx, rx) = mpsc::channel::<(usize, Film)>();
+    let mut workers = Vec::new();
+    for _ in 0..set.threads.max(1) {
+        let list = Arc::clone(&list);
+        let lights = Arc::clone(&lights);
+        let cam = Arc::clone(&cam);
+        let next = Arc::clone(&next);
+        let prev = Arc::clone(&prev);
+        let set = set.clone();
+        let bar = bar.clone();
+        let tx = tx.clone();
+        workers.push(thread::spawn(move || {
+            let mut smp:Box<dyn Sampler> = set.sampler.make(set.seed, set.samples as u64);
+            let wid:usize = set.width as usize;
+            let mut counts:Counters = Counters::default();
+            loop {
+                if set.deadline.map_or(false, |d| Instant::now() >= d) { break; }
+                let row:usize = next.fetch_add(1, Ordering::Relaxed);
+                if row >= set.height as usize { break; }
+                let band:Film = render_row(&*list, &lights, &cam, &set, row as i32, &prev[row * wid..(row + 1) * wid], upto, &mut *smp, &mut counts);
+                tx.send((row, band)).unwrap();
+                bar.inc(1);
+            }
+            counts.add(&stats::take());
+            counts
+        }));
+    }
+    drop(tx);
+
+    // bands that arrive early wait until the rows above them are in
+    let mut waiting:BTreeMap<usize, Film> = BTreeMap::new();
+    let mut done:usize = 0;
+    for (row, band) in rx {
+        waiting.insert(row, band);
+        while let Some(band) = waiting.remove(&done) {
+            film.merge(&band, done as i32 - set.filter.reach());
+            done += 1;
+        }
+    }
+    let mut counts:Counters = Counters::default();
+    for worker in workers {
+        counts.add(&worker.join().unwrap());
+    }
+    // out of time: the rows after a missing one are still waiting
+    for (row, band) in &waiting {
+        film.merge(band, *row as i32 - set.filter.reach());
+    }
+    (done == set.height as usize, counts)
+}
\ No newline at end of file
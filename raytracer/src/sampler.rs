@@ -0,0 +1,346 @@
+use crate::tools;
+use crate::tools::Rng;
+
+// Numbers in [0, 1) for one sample of one pixel. start() begins a sample and
+// every get1d/get2d after it takes the next dimension(s): the renderer asks for
+// the pixel offset, then the lens, the shutter time and the bounces in order.
+pub trait Sampler {
+    fn start(&mut self, pixel: u64, index: u64);
+    fn get1d(&mut self) -> f64;
+    fn get2d(&mut self) -> (f64, f64) { (self.get1d(), self.get1d()) }
+    fn randf(&mut self, low: f64, high: f64) -> f64 { low + (high - low) * self.get1d() }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    pub const NAMES:[&'static str; 4] = ["independent", "stratified", "halton", "sobol"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "independent" => Some(SamplerKind::Independent),
+            "stratified" => Some(SamplerKind::Stratified),
+            "halton" => Some(SamplerKind::Halton),
+            "sobol" => Some(SamplerKind::Sobol),
+            _ => None,
+        }
+    }
+
+    // `samples` is the expected count per pixel, the stratified sampler cuts its strata from it
+    pub fn make(&self, seed: u64, samples: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(Independent::new(seed)),
+            SamplerKind::Stratified => Box::new(Stratified::new(seed, samples)),
+            SamplerKind::Halton => Box::new(Halton::new(seed)),
+            SamplerKind::Sobol => Box::new(Sobol::new(seed)),
+        }
+    }
+}
+
+// the stream of (seed, pixel, index) straight from the Rng
+#[derive(Debug, Clone)]
+pub struct Independent {
+    seed: u64,
+    rng: Rng,
+}
+
+impl Independent {
+    pub fn new(seed: u64) -> Self { Self { seed, rng: Rng::for_sample(seed, 0, 0) } }
+}
+
+impl Sampler for Independent {
+    fn start(&mut self, pixel: u64, index: u64) { self.rng = Rng::for_sample(self.seed, pixel, index); }
+    fn get1d(&mut self) -> f64 { self.rng.next_f64() }
+}
+
+// hash for the per-pixel, per-dimension scrambles
+fn hash(seed: u64, pixel: u64, dim: u64) -> u64 {
+    tools::mix_seed(tools::mix_seed(seed, pixel), dim)
+}
+
+// Kensler, "Correlated Multi-Jittered Sampling": a permutation of 0..len picked by p
+fn permute(mut i: u32, len: u32, p: u32) -> u32 {
+    let mut w:u32 = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len { break; }
+    }
+    (i.wrapping_add(p)) % len
+}
+
+// Jittered strata over the expected sample count, shuffled per pixel and
+// dimension: 1D dimensions get n strata, 2D ones correlated multi-jittered
+// m x (n / m) strata. Samples past n start a new, differently shuffled round.
+#[derive(Debug, Clone)]
+pub struct Stratified {
+    seed: u64,
+    n: u32,
+    pixel: u64,
+    index: u64,
+    dim: u64,
+    rng: Rng,
+}
+
+impl Stratified {
+    pub fn new(seed: u64, samples: u64) -> Self {
+        let n:u32 = samples.max(1).min(1 << 24) as u32;
+        Self { seed, n, pixel: 0, index: 0, dim: 0, rng: Rng::for_sample(seed, 0, 0) }
+    }
+    // the stratum index within the round and a per-round scramble
+    fn slot(&self) -> (u32, u32) {
+        let round:u64 = self.index / self.n as u64;
+        let p:u32 = hash(self.seed, self.pixel, self.dim ^ (round << 32)) as u32;
+        ((self.index % self.n as u64) as u32, p)
+    }
+}
+
+impl Sampler for Stratified {
+    fn start(&mut self, pixel: u64, index: u64) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dim = 0;
+        self.rng = Rng::for_sample(self.seed, pixel, index);
+    }
+    fn get1d(&mut self) -> f64 {
+        let (s, p) = self.slot();
+        self.dim += 1;
+        (permute(s, self.n, p) as f64 + self.rng.next_f64()) / self.n as f64
+    }
+    fn get2d(&mut self) -> (f64, f64) {
+        let (s, p) = self.slot();
+        self.dim += 2;
+        let big:u32 = self.n;
+        let m:u32 = ((big as f64).sqrt() as u32).max(1);
+        let n:u32 = (big + m - 1) / m;
+        let s:u32 = permute(s, big, p.wrapping_mul(0x51633e2d));
+        let sx:u32 = permute(s % m, m, p.wrapping_mul(0x68bc21eb));
+        let sy:u32 = permute(s / m, n, p.wrapping_mul(0x02e5be93));
+        let jx:f64 = self.rng.next_f64();
+        let jy:f64 = self.rng.next_f64();
+        let x:f64 = (sx as f64 + (sy as f64 + jx) / n as f64) / m as f64;
+        let y:f64 = (s as f64 + jy) / big as f64;
+        (x.min(1.0 - f64::EPSILON), y.min(1.0 - f64::EPSILON))
+    }
+}
+
+const PRIMES:[u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+// digits of i in `base` mirrored about the radix point, each digit position
+// shuffled by its own permutation; the trailing zero digits are shuffled too,
+// down to the precision of an f64
+fn scrambled_radical_inverse(base: u64, mut i: u64, seed: u64) -> f64 {
+    let inv:f64 = 1.0 / base as f64;
+    let mut f:f64 = inv;
+    let mut out:f64 = 0.0;
+    let mut k:u64 = 0;
+    while f > 1e-16 {
+        let digit:u32 = (i % base) as u32;
+        out += permute(digit, base as u32, tools::mix_seed(seed, k) as u32) as f64 * f;
+        i /= base;
+        f *= inv;
+        k += 1;
+    }
+    out.min(1.0 - f64::EPSILON)
+}
+
+// dimension k is the radical inverse in the k-th prime, digit-scrambled per
+// pixel and dimension (plain Halton correlates badly in the larger primes);
+// past the prime table the dimensions are independent
+#[derive(Debug, Clone)]
+pub struct Halton {
+    seed: u64,
+    pixel: u64,
+    index: u64,
+    dim: usize,
+    rng: Rng,
+}
+
+impl Halton {
+    pub fn new(seed: u64) -> Self { Self { seed, pixel: 0, index: 0, dim: 0, rng: Rng::for_sample(seed, 0, 0) } }
+}
+
+impl Sampler for Halton {
+    fn start(&mut self, pixel: u64, index: u64) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dim = 0;
+        self.rng = Rng::for_sample(self.seed, pixel, index);
+    }
+    fn get1d(&mut self) -> f64 {
+        let dim:usize = self.dim;
+        self.dim += 1;
+        if dim >= PRIMES.len() { return self.rng.next_f64(); }
+        scrambled_radical_inverse(PRIMES[dim], self.index, hash(self.seed, self.pixel, dim as u64))
+    }
+}
+
+// generator matrices of the first four Sobol dimensions (Joe and Kuo's
+// direction numbers); column k is the contribution of bit k of the index
+fn sobol_matrices() -> [[u32; 32]; 4] {
+    let mut mat = [[0u32; 32]; 4];
+    for (k, v) in mat[0].iter_mut().enumerate() { *v = 1 << (31 - k); }
+    // degree s, coefficients a and initial m of each primitive polynomial
+    let polys:[(usize, u32, [u32; 3]); 3] = [(1, 0, [1, 0, 0]), (2, 1, [1, 3, 0]), (3, 1, [1, 3, 1])];
+    for (d, &(s, a, m)) in polys.iter().enumerate() {
+        let v:&mut [u32; 32] = &mut mat[d + 1];
+        for k in 0..s { v[k] = m[k] << (31 - k); }
+        for k in s..32 {
+            v[k] = v[k - s] ^ (v[k - s] >> s);
+            for j in 1..s {
+                if (a >> (s - 1 - j)) & 1 == 1 { v[k] ^= v[k - j]; }
+            }
+        }
+    }
+    mat
+}
+
+fn sobol(mat: &[u32; 32], mut i: u32) -> u32 {
+    let mut out:u32 = 0;
+    let mut k:usize = 0;
+    while i != 0 {
+        if i & 1 == 1 { out ^= mat[k]; }
+        i >>= 1;
+        k += 1;
+    }
+    out
+}
+
+// Burley, "Practical Hash-based Owen Scrambling"
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    let mut x:u32 = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+// Owen-scrambled Sobol points, four dimensions at a time: each group of four
+// has its own shuffle of the sample order and its own scrambles (Burley's
+// padding), so any number of dimensions can be drawn
+#[derive(Debug, Clone)]
+pub struct Sobol {
+    seed: u64,
+    mat: [[u32; 32]; 4],
+    pixel: u64,
+    index: u32,
+    dim: u64,
+}
+
+impl Sobol {
+    pub fn new(seed: u64) -> Self { Self { seed, mat: sobol_matrices(), pixel: 0, index: 0, dim: 0 } }
+}
+
+impl Sampler for Sobol {
+    fn start(&mut self, pixel: u64, index: u64) {
+        self.pixel = pixel;
+        self.index = index as u32;
+        self.dim = 0;
+    }
+    fn get1d(&mut self) -> f64 {
+        let group:u64 = self.dim / 4;
+        let d:usize = (self.dim % 4) as usize;
+        self.dim += 1;
+        let seed:u64 = hash(self.seed, self.pixel, group);
+        let i:u32 = owen_scramble(self.index, seed as u32);
+        let x:u32 = owen_scramble(sobol(&self.mat[d], i), (seed >> 32) as u32 ^ tools::mix_seed(seed, d as u64) as u32);
+        x as f64 / 4294967296.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS:[SamplerKind; 4] = [SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol];
+
+    // the first `dims` numbers of each of `n` samples of one pixel
+    fn draw(smp: &mut dyn Sampler, pixel: u64, n: u64, dims: usize) -> Vec<Vec<f64>> {
+        (0..n).map(|i| {
+            smp.start(pixel, i);
+            (0..dims).map(|_| smp.get1d()).collect()
+        }).collect()
+    }
+
+    // how many of the n strata of [0, 1) dimension `d` of the points fall in
+    fn strata_hit(points: &[Vec<f64>], d: usize) -> usize {
+        let n:usize = points.len();
+        let mut hit:Vec<bool> = vec![false; n];
+        for p in points { hit[(p[d] * n as f64) as usize] = true; }
+        hit.iter().filter(|&&h| h).count()
+    }
+
+    #[test]
+    fn repeats_a_sample_whatever_came_before() {
+        for kind in &KINDS {
+            let mut a:Box<dyn Sampler> = kind.make(5, 64);
+            let mut b:Box<dyn Sampler> = kind.make(5, 64);
+            draw(&mut *a, 3, 40, 12);
+            let first:Vec<Vec<f64>> = draw(&mut *a, 7, 16, 12);
+            assert_eq!(first, draw(&mut *b, 7, 16, 12), "{:?}", kind);
+            assert!(first.iter().flatten().all(|&x| x >= 0.0 && x < 1.0), "{:?}", kind);
+            // another pixel or seed gets other numbers
+            assert_ne!(first, draw(&mut *b, 8, 16, 12), "{:?}", kind);
+            assert_ne!(first, draw(&mut *kind.make(6, 64), 7, 16, 12), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn stratifies_every_dimension() {
+        for kind in &[SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol] {
+            let points:Vec<Vec<f64>> = draw(&mut *kind.make(1, 64), 11, 64, 10);
+            // Halton's higher bases only fill their strata over longer runs
+            let dims:usize = if *kind == SamplerKind::Halton { 1 } else { 10 };
+            for d in 0..dims {
+                assert_eq!(strata_hit(&points, d), 64, "{:?} dimension {}", kind, d);
+            }
+        }
+        // the independent one leaves about 1/e of them empty
+        let points:Vec<Vec<f64>> = draw(&mut *SamplerKind::Independent.make(1, 64), 11, 64, 1);
+        assert!(strata_hit(&points, 0) < 56);
+    }
+
+    #[test]
+    fn sobol_pairs_are_nets() {
+        // 256 points put one in each of the 16 x 16 cells of a pair of
+        // dimensions, in the first group of four and in a later one
+        let points:Vec<Vec<f64>> = draw(&mut Sobol::new(3), 2, 256, 6);
+        for &(a, b) in &[(0, 1), (4, 5)] {
+            let mut cells:Vec<usize> = vec![0; 256];
+            for p in &points { cells[(p[a] * 16.0) as usize * 16 + (p[b] * 16.0) as usize] += 1; }
+            assert!(cells.iter().all(|&c| c == 1), "dimensions {} and {}", a, b);
+        }
+    }
+}
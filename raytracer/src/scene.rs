@@ -0,0 +1,680 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::camera::Camera;
+use crate::shapes::Sphere;
+use crate::shapes::MovingSphere;
+use crate::shapes::XYRect;
+use crate::shapes::XZRect;
+use crate::shapes::YZRect;
+use crate::shapes::Cuboid;
+use crate::shapes::Hitlist;
+use crate::shapes::Hittable;
+use crate::shapes::Tagged;
+use crate::transform::Mat4;
+use crate::transform::Transform;
+use crate::bvh::BvhNode;
+use crate::obj;
+use crate::texture::Texture;
+use crate::texture::SolidColor;
+use crate::texture::Checker;
+use crate::texture::ImageTexture;
+use crate::texture::NoiseTexture;
+use crate::texture::NoiseKind;
+use crate::material::Material;
+use crate::material::Lamber;
+use crate::material::Metal;
+use crate::material::Dielectric;
+use crate::material::DiffuseLight;
+use crate::material::Isotropic;
+use crate::medium::ConstantMedium;
+use crate::render::Background;
+use crate::tools::Rng;
+
+// Scene files are line based, '#' starts a comment:
+//
+//   render width=1200 aspect=1.5 samples=700 depth=80
+//   camera lookfrom=12,2,3 lookat=0,0,0 vup=0,1,0 vfov=20 aperture=0.1 focus=10 time0=0 time1=1
+//   material ground lambertian color=0.5,0.5,0.5
+//   material steel metal color=0.7,0.6,0.5 fuzz=0.0
+//   material glass dielectric ior=1.5
+//   texture checks checker odd=0.2,0.3,0.1 even=0.9,0.9,0.9 scale=10
+//   material floor lambertian texture=checks     (metal also takes texture=)
+//   material lamp light color=4,4,4
+//   background bottom=1,1,1 top=0.5,0.7,1     (or: background color=0,0,0)
+//   sphere center=0,-1000,0 radius=1000 material=ground
+//   moving_sphere center0=0,1,0 center1=0,1.5,0 time0=0 time1=1 radius=0.2 material=ground
+//   xy_rect x0=0 x1=555 y0=0 y1=555 k=555 material=white    (also xz_rect, yz_rect)
+//   box min=130,0,65 max=295,165,230 material=white
+//   mesh file=models/teapot.obj material=white    (material is optional when the .obj has a .mtl)
+//
+// Any shape also takes scale=, rotate= (with axis=) and translate=, see place(),
+// and density= to turn it into a volume of fog, usually with an isotropic material:
+//
+//   material smoke isotropic color=0,0,0
+//   box min=0,0,0 max=165,330,165 material=smoke density=0.01
+//
+// Spheres, rectangles, boxes and meshes made of a light material are also sampled directly.
+//
+// Texture types are solid, checker (odd/even are colors or texture names), image
+// (file=), noise, turbulence and marble (scale=). Textures and materials must be
+// declared before they are used.
+
+#[derive(Debug)]
+pub struct Scene {
+    pub list: Hitlist,
+    pub lights: Hitlist,    // also in list, sampled directly by the renderer
+    pub view: CamDesc,
+    pub width: i32,
+    pub height: i32,
+    pub samples: i32,
+    pub maxdeep: i32,
+    pub background: Background,
+    pub fog: bool,          // some shape is a volume
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneError {
+    pub line: usize,    // 0: not tied to a line
+    pub msg: String,
+}
+
+impl SceneError {
+    pub fn new(line: usize, msg: String) -> Self { Self { line, msg } }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 { write!(f, "{}", self.msg) } else { write!(f, "line {}: {}", self.line, self.msg) }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl Scene {
+    // the aspect ratio follows the image size, which may be overridden after loading
+    pub fn camera(&self) -> Camera {
+        let v:&CamDesc = &self.view;
+        let aspect:f64 = self.width as f64 / self.height as f64;
+        let focus:f64 = v.focus.unwrap_or((v.lookfrom.clone() - v.lookat.clone()).length());
+        Camera::new(v.lookfrom.clone(), v.lookat.clone(), v.vup.clone(), v.vfov, aspect, v.aperture, focus, v.time0, v.time1)
+    }
+}
+
+// key=value pairs of one directive, taken out one by one so leftovers can be reported
+struct Fields {
+    line: usize,
+    kind: String,
+    map: HashMap<String, String>,
+}
+
+impl Fields {
+    fn parse(line: usize, kind: &str, words: &[&str]) -> Result<Self, SceneError> {
+        let mut map:HashMap<String, String> = HashMap::new();
+        for word in words {
+            let mut kv = word.splitn(2, '=');
+            let key:&str = kv.next().unwrap();
+            let val:&str = match kv.next() {
+                Some(v) if !key.is_empty() && !v.is_empty() => v,
+                _ => return Err(SceneError::new(line, format!("expected key=value in `{}`, found `{}`", kind, word))),
+            };
+            if map.insert(key.to_string(), val.to_string()).is_some() {
+                return Err(SceneError::new(line, format!("field `{}` given twice in `{}`", key, kind)));
+            }
+        }
+        Ok(Self { line, kind: kind.to_string(), map })
+    }
+
+    fn err(&self, msg: String) -> SceneError { SceneError::new(self.line, msg) }
+
+    fn take(&mut self, key: &str) -> Option<String> { self.map.remove(key) }
+
+    fn need(&mut self, key: &str) -> Result<String, SceneError> {
+        match self.take(key) {
+            Some(v) => Ok(v),
+            None => Err(self.err(format!("missing field `{}` in `{}`", key, self.kind))),
+        }
+    }
+
+    fn to_f64(&self, key: &str, val: &str) -> Result<f64, SceneError> {
+        match val.parse::<f64>() {
+            Ok(x) if x.is_finite() => Ok(x),
+            _ => Err(self.err(format!("field `{}` in `{}`: expected a number, found `{}`", key, self.kind, val))),
+        }
+    }
+
+    fn to_vec(&self, key: &str, val: &str) -> Result<Vec3, SceneError> {
+        let parts:Vec<&str> = val.split(',').collect();
+        if parts.len() != 3 {
+            return Err(self.err(format!("field `{}` in `{}`: expected x,y,z, found `{}`", key, self.kind, val)));
+        }
+        Ok(Vec3::new(self.to_f64(key, parts[0])?, self.to_f64(key, parts[1])?, self.to_f64(key, parts[2])?))
+    }
+
+    fn f64_or(&mut self, key: &str, def: f64) -> Result<f64, SceneError> {
+        match self.take(key) {
+            Some(v) => self.to_f64(key, &v),
+            None => Ok(def),
+        }
+    }
+
+    fn need_f64(&mut self, key: &str) -> Result<f64, SceneError> {
+        let v:String = self.need(key)?;
+        self.to_f64(key, &v)
+    }
+
+    fn need_vec(&mut self, key: &str) -> Result<Vec3, SceneError> {
+        let v:String = self.need(key)?;
+        self.to_vec(key, &v)
+    }
+
+    fn vec_or(&mut self, key: &str, def: Vec3) -> Result<Vec3, SceneError> {
+        match self.take(key) {
+            Some(v) => self.to_vec(key, &v),
+            None => Ok(def),
+        }
+    }
+
+    fn int_or(&mut self, key: &str, def: i32) -> Result<i32, SceneError> {
+        match self.take(key) {
+            Some(v) => match v.parse::<i32>() {
+                Ok(x) if x > 0 => Ok(x),
+                _ => Err(self.err(format!("field `{}` in `{}`: expected a positive integer, found `{}`", key, self.kind, v))),
+            },
+            None => Ok(def),
+        }
+    }
+
+    fn finish(self) -> Result<(), SceneError> {
+        let mut keys:Vec<&String> = self.map.keys().collect();
+        keys.sort();
+        match keys.first() {
+            Some(key) => Err(self.err(format!("unknown field `{}` in `{}`", key, self.kind))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CamDesc {
+    pub lookfrom: Vec3,
+    pub lookat: Vec3,
+    pub vup: Vec3,
+    pub vfov: f64,
+    pub aperture: f64,
+    pub focus: Option<f64>,    // None: focus on lookat
+    pub time0: f64,     // shutter interval
+    pub time1: f64,
+}
+
+// a texture name, or r,g,b for a solid color
+fn find_texture(f: &Fields, key: &str, val: &str, texs: &HashMap<String, Arc<dyn Texture>>) -> Result<Arc<dyn Texture>, SceneError> {
+    if val.contains(',') {
+        return Ok(Arc::new(SolidColor::new(f.to_vec(key, val)?)));
+    }
+    match texs.get(val) {
+        Some(tex) => Ok(tex.clone()),
+        None => Err(f.err(format!("texture `{}` is not defined", val))),
+    }
+}
+
+// color=r,g,b or texture=name
+fn albedo(f: &mut Fields, texs: &HashMap<String, Arc<dyn Texture>>) -> Result<Arc<dyn Texture>, SceneError> {
+    match (f.take("color"), f.take("texture")) {
+        (Some(c), None) => Ok(Arc::new(SolidColor::new(f.to_vec("color", &c)?))),
+        (None, Some(t)) => find_texture(f, "texture", &t, texs),
+        (Some(_), Some(_)) => Err(f.err(format!("`{}` takes either `color` or `texture`, not both", f.kind))),
+        (None, None) => Err(f.err(format!("missing field `color` (or `texture`) in `{}`", f.kind))),
+    }
+}
+
+fn parse_texture(f: &mut Fields, kind: &str, texs: &HashMap<String, Arc<dyn Texture>>, dir: &Path, rng: &mut Rng) -> Result<Arc<dyn Texture>, SceneError> {
+    let tex:Arc<dyn Texture> = match kind {
+        "solid" => Arc::new(SolidColor::new(f.need_vec("color")?)),
+        "checker" => {
+            let odd:String = f.need("odd")?;
+            let even:String = f.need("even")?;
+            let odd:Arc<dyn Texture> = find_texture(f, "odd", &odd, texs)?;
+            let even:Arc<dyn Texture> = find_texture(f, "even", &even, texs)?;
+            Arc::new(Checker::new(odd, even, f.f64_or("scale", 10.0)?))
+        }
+        "image" => {
+            let file:String = f.need("file")?;
+            match ImageTexture::load(&dir.join(&file).to_string_lossy()) {
+                Ok(img) => Arc::new(img),
+                Err(e) => return Err(f.err(e)),
+            }
+        }
+        "noise" => Arc::new(NoiseTexture::new(f.f64_or("scale", 1.0)?, NoiseKind::Noise, rng)),
+        "turbulence" => Arc::new(NoiseTexture::new(f.f64_or("scale", 1.0)?, NoiseKind::Turbulence, rng)),
+        "marble" => Arc::new(NoiseTexture::new(f.f64_or("scale", 1.0)?, NoiseKind::Marble, rng)),
+        _ => return Err(f.err(format!("unknown texture type `{}`", kind))),
+    };
+    Ok(tex)
+}
+
+fn parse_material(f: &mut Fields, kind: &str, texs: &HashMap<String, Arc<dyn Texture>>) -> Result<Arc<dyn Material>, SceneError> {
+    let mat:Arc<dyn Material> = match kind {
+        "lambertian" => Arc::new(Lamber::new_tex(albedo(f, texs)?)),
+        "metal" => Arc::new(Metal::new_tex(albedo(f, texs)?, f.f64_or("fuzz", 0.0)?)),
+        "dielectric" => Arc::new(Dielectric::new(f.need_f64("ior")?)),
+        "light" => Arc::new(DiffuseLight::new(f.need_vec("color")?)),
+        "isotropic" => Arc::new(Isotropic::new_tex(albedo(f, texs)?)),
+        _ => return Err(f.err(format!("unknown material type `{}`", kind))),
+    };
+    Ok(mat)
+}
+
+fn find_material(f: &mut Fields, mats: &HashMap<String, Arc<dyn Material>>) -> Result<Arc<dyn Material>, SceneError> {
+    let name:String = f.need("material")?;
+    match mats.get(&name) {
+        Some(mat) => Ok(mat.clone()),
+        None => Err(f.err(format!("material `{}` is not defined", name))),
+    }
+}
+
+const SHAPES:[&str; 7] = ["sphere", "moving_sphere", "xy_rect", "xz_rect", "yz_rect", "box", "mesh"];
+
+// shapes that implement pdf_value/random, so they can be sampled as lights
+const SAMPLED:[&str; 6] = ["sphere", "xy_rect", "xz_rect", "yz_rect", "box", "mesh"];
+
+// the shape and, for a mesh, its faces that emit light by their own material
+fn parse_shape(kind: &str, f: &mut Fields, mats: &HashMap<String, Arc<dyn Material>>, dir: &Path) -> Result<(Arc<dyn Hittable>, Option<Arc<dyn Hittable>>), SceneError> {
+    let mut glow:Option<Arc<dyn Hittable>> = None;
+    let shape:Arc<dyn Hittable> = match kind {
+        "sphere" => {
+            let ct:Vec3 = f.need_vec("center")?;
+            let rad:f64 = f.need_f64("radius")?;
+            Arc::new(Sphere::new(ct, rad, find_material(f, mats)?))
+        }
+        "moving_sphere" => {
+            let ct0:Vec3 = f.need_vec("center0")?;
+            let ct1:Vec3 = f.need_vec("center1")?;
+            let time0:f64 = f.f64_or("time0", 0.0)?;
+            let time1:f64 = f.f64_or("time1", 1.0)?;
+            if time1 <= time0 { return Err(f.err("`moving_sphere` needs time0 < time1".to_string())); }
+            let rad:f64 = f.need_f64("radius")?;
+            Arc::new(MovingSphere::new(ct0, ct1, time0, time1, rad, find_material(f, mats)?))
+        }
+        "box" => {
+            let p0:Vec3 = f.need_vec("min")?;
+            let p1:Vec3 = f.need_vec("max")?;
+            Arc::new(Cuboid::new(p0, p1, find_material(f, mats)?))
+        }
+        "mesh" => {
+            let file:String = f.need("file")?;
+            let mat:Option<Arc<dyn Material>> = if f.map.contains_key("material") { Some(find_material(f, mats)?) } else { None };
+            // its errors already tell the file and line
+            let (tris, lamps):(Hitlist, Hitlist) = obj::load(&dir.join(&file), mat)?;
+            if !lamps.shapes.is_empty() { glow = Some(Arc::new(BvhNode::new(lamps))); }
+            Arc::new(BvhNode::new(tris))
+        }
+        _ => {
+            let (a, b):(&str, &str) = match kind {
+                "xy_rect" => ("x", "y"),
+                "xz_rect" => ("x", "z"),
+                _ => ("y", "z"),
+            };
+            let a0:f64 = f.need_f64(&format!("{}0", a))?;
+            let a1:f64 = f.need_f64(&format!("{}1", a))?;
+            let b0:f64 = f.need_f64(&format!("{}0", b))?;
+            let b1:f64 = f.need_f64(&format!("{}1", b))?;
+            let k:f64 = f.need_f64("k")?;
+            if a0 >= a1 || b0 >= b1 {
+                return Err(f.err(format!("`{}` needs {}0 < {}1 and {}0 < {}1", kind, a, a, b, b)));
+            }
+            let mat:Arc<dyn Material> = find_material(f, mats)?;
+            match kind {
+                "xy_rect" => Arc::new(XYRect::new(a0, a1, b0, b1, k, mat)),
+                "xz_rect" => Arc::new(XZRect::new(a0, a1, b0, b1, k, mat)),
+                _ => Arc::new(YZRect::new(a0, a1, b0, b1, k, mat)),
+            }
+        }
+    };
+    Ok((shape, glow))
+}
+
+// optional scale=s|x,y,z, rotate=degrees about axis=x,y,z (default 0,1,0) and
+// translate=x,y,z on any shape, applied in that order; None if none is given
+fn place(f: &mut Fields) -> Result<Option<Mat4>, SceneError> {
+    let mut m:Mat4 = Mat4::identity();
+    let mut moved:bool = false;
+    if let Some(v) = f.take("scale") {
+        let s:Vec3 = if v.contains(',') {
+            f.to_vec("scale", &v)?
+        } else {
+            let k:f64 = f.to_f64("scale", &v)?;
+            Vec3::new(k, k, k)
+        };
+        if s.x == 0.0 || s.y == 0.0 || s.z == 0.0 {
+            return Err(f.err(format!("field `scale` in `{}` must not be zero", f.kind)));
+        }
+        m = Mat4::scale(s) * m;
+        moved = true;
+    }
+    let axis:Option<String> = f.take("axis");
+    match f.take("rotate") {
+        Some(v) => {
+            let degree:f64 = f.to_f64("rotate", &v)?;
+            let axis:Vec3 = match axis {
+                Some(a) => f.to_vec("axis", &a)?,
+                None => Vec3::new(0.0, 1.0, 0.0),
+            };
+            if axis.squared_length() == 0.0 {
+                return Err(f.err(format!("field `axis` in `{}` must not be zero", f.kind)));
+            }
+            m = Mat4::rotate(axis, degree) * m;
+            moved = true;
+        }
+        None => if axis.is_some() {
+            return Err(f.err(format!("field `axis` in `{}` needs `rotate`", f.kind)));
+        },
+    }
+    if let Some(v) = f.take("translate") {
+        m = Mat4::translate(f.to_vec("translate", &v)?) * m;
+        moved = true;
+    }
+    Ok(if moved { Some(m) } else { None })
+}
+
+// `shape` moved by what place() read
+fn placed(shape: Arc<dyn Hittable>, m: &Option<Mat4>) -> Arc<dyn Hittable> {
+    match m {
+        Some(m) => Arc::new(Transform::new(shape, m.clone())),
+        None => shape,
+    }
+}
+
+// paths in the scene (meshes) are relative to `dir`
+pub fn parse(text: &str, dir: &Path, rng: &mut Rng) -> Result<Scene, SceneError> {
+    let mut list:Hitlist = Hitlist::new();
+    let mut lights:Hitlist = Hitlist::new();
+    let mut mats:HashMap<String, Arc<dyn Material>> = HashMap::new();
+    let mut lamps:Vec<String> = Vec::new();    // names of the light materials
+    let mut mat_ids:HashMap<String, u32> = HashMap::new();  // from 1, in order of definition
+    let mut texs:HashMap<String, Arc<dyn Texture>> = HashMap::new();
+    let mut cam:Option<CamDesc> = None;
+    let mut width:i32 = 1200;
+    let mut aspect:f64 = 3.0 / 2.0;
+    let mut samples:i32 = 100;
+    let mut maxdeep:i32 = 50;
+    let mut background:Background = Background::sky();
+    let mut has_fog:bool = false;
+
+    for (idx, raw) in text.lines().enumerate() {
+        let line:usize = idx + 1;
+        let content:&str = raw.split('#').next().unwrap();
+        let words:Vec<&str> = content.split_whitespace().collect();
+        if words.is_empty() { continue; }
+        match words[0] {
+            "render" => {
+                let mut f = Fields::parse(line, "render", &words[1..])?;
+                width = f.int_or("width", width)?;
+                aspect = f.f64_or("aspect", aspect)?;
+                if aspect <= 0.0 { return Err(f.err("field `aspect` in `render` must be positive".to_string())); }
+                samples = f.int_or("samples", samples)?;
+                maxdeep = f.int_or("depth", maxdeep)?;
+                f.finish()?;
+            }
+            "background" => {
+                let mut f = Fields::parse(line, "background", &words[1..])?;
+                background = match f.take("color") {
+                    Some(v) => Background::Solid(f.to_vec("color", &v)?),
+                    None => Background::Gradient(f.need_vec("bottom")?, f.need_vec("top")?),
+                };
+                f.finish()?;
+            }
+            "camera" => {
+                if cam.is_some() { return Err(SceneError::new(line, "camera given twice".to_string())); }
+                let mut f = Fields::parse(line, "camera", &words[1..])?;
+                cam = Some(CamDesc {
+                    lookfrom: f.need_vec("lookfrom")?,
+                    lookat: f.need_vec("lookat")?,
+                    vup: f.vec_or("vup", Vec3::new(0.0, 1.0, 0.0))?,
+                    vfov: f.f64_or("vfov", 40.0)?,
+                    aperture: f.f64_or("aperture", 0.0)?,
+                    focus: match f.take("focus") {
+                        Some(v) => Some(f.to_f64("focus", &v)?),
+                        None => None,
+                    },
+                    time0: f.f64_or("time0", 0.0)?,
+                    time1: f.f64_or("time1", 0.0)?,
+                });
+                if let Some(c) = &cam {
+                    if c.time1 < c.time0 { return Err(f.err("camera shutter closes (time1) before it opens (time0)".to_string())); }
+                }
+                f.finish()?;
+            }
+            "texture" => {
+                if words.len() < 3 {
+                    return Err(SceneError::new(line, "expected `texture <name> <type> key=value...`".to_string()));
+                }
+                let name:String = words[1].to_string();
+                let mut f = Fields::parse(line, &format!("texture {}", name), &words[3..])?;
+                let tex:Arc<dyn Texture> = parse_texture(&mut f, words[2], &texs, dir, rng)?;
+                f.finish()?;
+                if texs.insert(name.clone(), tex).is_some() {
+                    return Err(SceneError::new(line, format!("texture `{}` defined twice", name)));
+                }
+            }
+            "material" => {
+                if words.len() < 3 {
+                    return Err(SceneError::new(line, "expected `material <name> <type> key=value...`".to_string()));
+                }
+                let name:String = words[1].to_string();
+                let mut f = Fields::parse(line, &format!("material {}", name), &words[3..])?;
+                let mat:Arc<dyn Material> = parse_material(&mut f, words[2], &texs)?;
+                f.finish()?;
+                if mats.insert(name.clone(), mat).is_some() {
+                    return Err(SceneError::new(line, format!("material `{}` defined twice", name)));
+                }
+                mat_ids.insert(name.clone(), mat_ids.len() as u32 + 1);
+                if words[2] == "light" { lamps.push(name); }
+            }
+            kind if SHAPES.contains(&kind) => {
+                let mut f = Fields::parse(line, kind, &words[1..])?;
+                // density= fills the shape with fog that scatters through its material
+                let fog:Option<(f64, Arc<dyn Material>)> = match f.take("density") {
+                    Some(v) => {
+                        let density:f64 = f.to_f64("density", &v)?;
+                        if density <= 0.0 { return Err(f.err(format!("field `density` in `{}` must be positive", kind))); }
+                        // the boundary keeps its material= too, so only peek at it here
+                        let phase:Arc<dyn Material> = match f.map.get("material").and_then(|name| mats.get(name)) {
+                            Some(mat) => mat.clone(),
+                            None => return Err(f.err(format!("`{}` with `density` needs a defined material", kind))),
+                        };
+                        Some((density, phase))
+                    }
+                    None => None,
+                };
+                let lamp:bool = fog.is_none() && SAMPLED.contains(&kind)
+                    && f.map.get("material").map_or(false, |name| lamps.contains(name));
+                let mtl:u32 = f.map.get("material").and_then(|name| mat_ids.get(name)).cloned().unwrap_or(0);
+                let obj:u32 = list.shapes.len() as u32 + 1;
+                let (shape, glow):(Arc<dyn Hittable>, Option<Arc<dyn Hittable>>) = parse_shape(kind, &mut f, &mats, dir)?;
+                let m:Option<Mat4> = place(&mut f)?;
+                let shape:Arc<dyn Hittable> = placed(shape, &m);
+                f.finish()?;
+                match fog {
+                    Some((density, phase)) => {
+                        let fog:Arc<dyn Hittable> = Arc::new(ConstantMedium::new(shape, density, phase, obj as u64));
+                        list.add(Arc::new(Tagged::new(fog, obj, mtl)));
+                        has_fog = true;
+                    }
+                    None => {
+                        if lamp { lights.add(shape.clone()); }
+                        if let Some(glow) = glow { lights.add(placed(glow, &m)); }
+                        list.add(Arc::new(Tagged::new(shape, obj, mtl)));
+                    }
+                }
+            }
+            other => return Err(SceneError::new(line, format!("unknown directive `{}`", other))),
+        }
+    }
+
+    let view:CamDesc = match cam {
+        Some(c) => c,
+        None => return Err(SceneError::new(0, "scene has no camera".to_string())),
+    };
+    if list.shapes.is_empty() {
+        return Err(SceneError::new(0, "scene has no shapes".to_string()));
+    }
+    let height:i32 = ((width as f64 / aspect) as i32).max(1);
+    Ok(Scene { list, lights, view, width, height, samples, maxdeep, background, fog: has_fog })
+}
+
+pub fn load(path: &str, rng: &mut Rng) -> Result<Scene, SceneError> {
+    match fs::read_to_string(path) {
+        Ok(text) => parse(&text, Path::new(path).parent().unwrap_or_else(|| Path::new("")), rng),
+        Err(e) => Err(SceneError::new(0, format!("cannot read `{}`: {}", path, e))),
+    }
+}
+
+// the book 1 cover, used when no scene file is given
+pub fn cover(rng: &mut Rng) -> Scene {
+    const AS_RATIO:f64 = 3.0 / 2.0;
+    const I_WID:i32 = 1200;
+    const I_HIT:i32 = (I_WID as f64 / AS_RATIO) as i32;
+    const SAMPLES:i32 = 700; //500
+    const MAXDEEP:i32 = 80; //50
+
+    let mut list:Hitlist = Hitlist::new();
+    let mat_g:Lamber = Lamber::new(Color::new(0.5, 0.5, 0.5)); // 0.5
+    let arc_g = Arc::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, mat_g.clone()));
+    list.add(arc_g);
+
+    let mut a:i32 = -11;
+    while a < 11 {
+        let mut b:i32 = -11;
+        while b < 11 {
+            let chmat:f64 = rng.randf(0.0, 1.0);
+            let ct:Vec3 = Vec3::new(a as f64 + 0.9 * rng.randf(0.0, 1.0), 0.2, b as f64 + 0.9 * rng.randf(0.0, 1.0));
+
+            if (ct.clone() - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                if chmat < 0.8 {
+                    let lbc:Color = Color::elemul(Color::randv(rng), Color::randv(rng));
+                    let mat:Lamber = Lamber::new(lbc);
+                    let arc_s = Arc::new(Sphere::new(ct.clone(), 0.2, mat));
+                    list.add(arc_s);
+                } else if chmat < 0.95 {
+                    let lbc:Color = Color::randvr(rng, 0.5, 1.0);
+                    let fuzz:f64 = rng.randf(0.0, 0.5); //0.0 -> 0.5
+                    let mat:Metal = Metal::new(lbc, fuzz);
+                    let arc_s = Arc::new(Sphere::new(ct.clone(), 0.2, mat));
+                    list.add(arc_s);
+                } else {
+                    let mat:Dielectric = Dielectric::new(1.5);
+                    let arc_s = Arc::new(Sphere::new(ct.clone(), 0.2, mat));
+                    list.add(arc_s);
+                }
+            }
+            b += 1;
+        }
+        a += 1;
+    }
+
+    let mat_1:Dielectric = Dielectric::new(1.5);
+    let arc_s1 = Arc::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, mat_1));
+    list.add(arc_s1);
+
+    let mat_2:Lamber = Lamber::new(Color::new(0.4, 0.2, 0.1));
+    let arc_s2 = Arc::new(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, mat_2));
+    list.add(arc_s2);
+
+    let mat_3:Metal = Metal::new(Color::new(0.7, 0.6, 0.5), 0.0);
+    let arc_s3 = Arc::new(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, mat_3));
+    list.add(arc_s3);
+
+    let view:CamDesc = CamDesc {
+        lookfrom: Vec3::new(12.0, 2.0, 3.0),
+        lookat: Vec3::new(0.0, 0.0, 0.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov: 20.0,
+        aperture: 0.1,
+        focus: Some(10.0),
+        time0: 0.0,
+        time1: 0.0,
+    };
+
+    // every sphere has a material of its own, so the ids match
+    let mut tagged:Hitlist = Hitlist::new();
+    for (k, shape) in list.shapes.into_iter().enumerate() {
+        tagged.add(Arc::new(Tagged::new(shape, k as u32 + 1, k as u32 + 1)));
+    }
+    Scene { list: tagged, lights: Hitlist::new(), view, width: I_WID, height: I_HIT, samples: SAMPLES, maxdeep: MAXDEEP, background: Background::sky(), fog: false }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::temp_path;
+
+    const CAMERA:&str = "camera lookfrom=0,0,5 lookat=0,0,0\n";
+
+    fn parse_str(text: &str) -> Result<Scene, SceneError> {
+        parse(text, Path::new(""), &mut Rng::new(1, 0))
+    }
+
+    fn error(text: &str) -> SceneError {
+        match parse_str(text) {
+            Ok(_) => panic!("`{}` should not load", text),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn loads_a_small_scene() {
+        let text:String = format!("render width=300 aspect=1.5 samples=8 depth=4\n{}\
+            material red lambertian color=0.8,0.1,0.1\n\
+            material lamp light color=4,4,4\n\
+            sphere center=0,0,0 radius=1 material=red   # a comment\n\
+            xz_rect x0=-1 x1=1 z0=-1 z1=1 k=3 material=lamp\n", CAMERA);
+        let scene:Scene = parse_str(&text).unwrap();
+        assert_eq!((scene.width, scene.height, scene.samples, scene.maxdeep), (300, 200, 8, 4));
+        assert_eq!(scene.list.shapes.len(), 2);
+        assert_eq!(scene.lights.shapes.len(), 1);
+    }
+
+    #[test]
+    fn reports_the_line_of_an_error() {
+        let e:SceneError = error(&format!("{}\nsphere center=0,0,0 radius=1 material=nope\n", CAMERA));
+        assert_eq!(e.line, 3);
+        assert!(e.msg.contains("nope"), "{}", e);
+        assert_eq!(error(&format!("{}sphere center=0,0 radius=1\n", CAMERA)).line, 2);
+        assert_eq!(error(&format!("{}teapot\n", CAMERA)).msg, "unknown directive `teapot`");
+        assert!(error(&format!("{}material m lambertian color=1,1,1 color=0,0,0\n", CAMERA)).msg.contains("twice"));
+    }
+
+    #[test]
+    fn samples_the_glowing_faces_of_a_mesh() {
+        let (obj, mtl):(String, String) = (temp_path("glow.obj"), temp_path("glow.mtl"));
+        fs::write(&mtl, "newmtl lamp\nKe 5 5 5\nnewmtl wall\nKd 0.5 0.5 0.5\n").unwrap();
+        fs::write(&obj, format!("mtllib {}\nv 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\n\
+            usemtl wall\nf 1 2 3\nusemtl lamp\nf 2 4 3\n", Path::new(&mtl).file_name().unwrap().to_string_lossy())).unwrap();
+        let scene:Scene = parse_str(&format!("{}mesh file={} scale=2 translate=0,1,0\n", CAMERA, obj)).unwrap();
+        assert_eq!(scene.lights.shapes.len(), 1);
+        // only the emitting face is sampled, where it was moved to
+        let toward:Vec3 = Vec3::new(1.0, 2.5, 0.0) - Vec3::new(1.0, 2.5, 5.0);
+        assert!(scene.lights.pdf_value(&Vec3::new(1.0, 2.5, 5.0), &toward) > 0.0);
+        let toward:Vec3 = Vec3::new(0.5, 1.5, 0.0) - Vec3::new(0.5, 1.5, 5.0);
+        assert_eq!(scene.lights.pdf_value(&Vec3::new(0.5, 1.5, 5.0), &toward), 0.0);
+
+        // the .obj's own errors come through as they are
+        fs::write(&obj, "v 0 0 0\nf 1 2 3\n").unwrap();
+        let e:SceneError = error(&format!("{}mesh file={}\n", CAMERA, obj));
+        assert_eq!(e.to_string(), format!("{}:2: index 2 out of range (1 defined)", obj));
+        fs::remove_file(&obj).unwrap();
+        fs::remove_file(&mtl).unwrap();
+    }
+
+    #[test]
+    fn needs_a_camera_and_a_shape() {
+        assert_eq!(error("material m lambertian color=1,1,1\nsphere center=0,0,0 radius=1 material=m\n").msg, "scene has no camera");
+        assert_eq!(error(CAMERA).msg, "scene has no shapes");
+    }
+}
\ No newline at end of file
@@ -2,7 +2,15 @@ use std::fmt::Debug;
 use std::vec::Vec;
 use std::sync::Arc;
 use crate::vec3::Vec3;
+use crate::vec3::Onb;
 use crate::ray::Ray;
+use crate::aabb::Aabb;
+use crate::tools::PI;
+use crate::tools::INF;
+use crate::sampler::Sampler;
+use crate::stats;
+use crate::stats::Prim;
+use crate::vec3;
 use crate::material::Neg;
 use crate::material::Material;
 
@@ -11,8 +19,12 @@ pub struct Hitrec <'a> {
     pub p: Vec3,
     pub nf: Vec3,
     pub t: f64,
+    pub u: f64,     // surface coordinates
+    pub v: f64,
     pub front_face: bool,   // true: hit outsides
     pub mat: &'a dyn Material,
+    pub obj: u32,           // object and material ids, 0 until a Tagged sets them
+    pub mtl: u32,
 }
 
 impl <'a> Hitrec <'a> {
@@ -21,8 +33,12 @@ impl <'a> Hitrec <'a> {
             p: Vec3::new(0.0, 0.0, 0.0),
             nf: Vec3::new(0.0, 0.0, 0.0),
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
             front_face: false,
             mat: nmat,
+            obj: 0,
+            mtl: 0,
         }
     }
     pub fn p(&self) -> Vec3 { self.p.clone() }
@@ -35,8 +51,12 @@ impl <'a> Hitrec <'a> {
         self.p = rec.p.clone();
         self.nf = rec.nf.clone();
         self.t = rec.t;
+        self.u = rec.u;
+        self.v = rec.v;
         self.front_face = rec.front_face;
         self.mat = rec.mat;
+        self.obj = rec.obj;
+        self.mtl = rec.mtl;
     }
 }
 
@@ -54,13 +74,62 @@ impl <M: Material> Sphere <M> {
     pub fn ct(&self) -> Vec3 { self.ct.clone() }
 }
 
-pub trait Hittable: Debug {
+// p on the unit sphere; u is the angle around y from x = -1, v from y = -1 to y = 1
+pub fn sphere_uv(p: &Vec3) -> (f64, f64) {
+    let theta:f64 = (-p.y).max(-1.0).min(1.0).acos();
+    let phi:f64 = (-p.z).atan2(p.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+fn hit_sphere<'a>(ct: &Vec3, rad: f64, mat: &'a dyn Material, r: Ray, t_min: f64, t_max: f64) -> Option<Hitrec<'a>> {
+    let oc:Vec3 = r.origin() - ct.clone();
+    let a:f64 = r.diraction().squared_length();
+    let h:f64 = (r.diraction()*oc.clone());
+    let c:f64 = (oc.squared_length()) - rad*rad;
+    let dis:f64 = h*h - a*c;
+    let mut rec:Hitrec = Hitrec::new(mat);
+    if dis <= 0.0 { return None; }
+    else {
+        let root:f64 = dis.sqrt();
+        let mut t:f64 = (-h - root) / a;
+        if (t > t_min && t < t_max) {
+            rec.t = t;
+            rec.p = r.at(t);
+            let nf:Vec3 = (rec.p() - ct.clone()) / rad;
+            let (u, v) = sphere_uv(&nf);
+            rec.u = u;
+            rec.v = v;
+            rec.set_face(r.clone(), nf);
+            return Some(rec); 
+        }
+        t = (-h + root) / a;
+        if (t > t_min && t < t_max) {
+            rec.t = t;
+            rec.p = r.at(t);
+            let nf:Vec3 = (rec.p() - ct.clone()) / rad;
+            let (u, v) = sphere_uv(&nf);
+            rec.u = u;
+            rec.v = v;
+            rec.set_face(r.clone(), nf);
+            return Some(rec); 
+        }
+        return None;
+    }
+}
+
+pub trait Hittable: Debug + Send + Sync {
     fn hit (&self, r: Ray, t_min: f64, t_max: f64) -> Option<Hitrec>;
+    fn bounding_box(&self) -> Option<Aabb>;
+    // for sampling the shape as a light: the density, per unit solid angle seen
+    // from o, of directions v toward the shape, and a direction drawn from it;
+    // shapes that keep these defaults cannot be sampled
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 { 0.0 }
+    fn random(&self, o: &Vec3, smp: &mut dyn Sampler) -> Vec3 { Vec3::new(1.0, 0.0, 0.0) }
 }
 
 #[derive(Debug)]
 pub struct Hitlist {
-    pub shapes: Vec<Arc<Hittable>>,
+    pub shapes: Vec<Arc<dyn Hittable>>,
 }
 
 impl Hitlist {
@@ -68,7 +137,41 @@ impl Hitlist {
         Self { shapes : Vec::new() }
     }
     pub fn clear(&mut self) { self.shapes.clear(); }
-    pub fn add(&mut self, shape: Arc<Hittable>) { self.shapes.push(shape); }
+    pub fn add(&mut self, shape: Arc<dyn Hittable>) { self.shapes.push(shape); }
+}
+
+// center moves linearly from ct0 at time0 to ct1 at time1
+#[derive(Debug)]
+pub struct MovingSphere <M: Material> {
+    pub ct0: Vec3,
+    pub ct1: Vec3,
+    pub time0: f64,
+    pub time1: f64,
+    pub rad: f64,
+    pub mat: M,
+}
+
+impl <M: Material> MovingSphere <M> {
+    pub fn new(ct0: Vec3, ct1: Vec3, time0: f64, time1: f64, rad: f64, mat: M) -> Self {
+        Self { ct0, ct1, time0, time1, rad, mat }
+    }
+    pub fn ct(&self, tm: f64) -> Vec3 {
+        self.ct0.clone() + (self.ct1.clone() - self.ct0.clone()) * ((tm - self.time0) / (self.time1 - self.time0))
+    }
+}
+
+impl <M: Material> Hittable for MovingSphere <M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<Hitrec> {
+        stats::test(Prim::MovingSphere);
+        hit_sphere(&self.ct(r.time()), self.rad, &self.mat, r, t_min, t_max)
+    }
+    // the whole path, whatever the shutter interval
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r:Vec3 = Vec3::new(self.rad.abs(), self.rad.abs(), self.rad.abs());
+        let b0:Aabb = Aabb::new(self.ct0.clone() - r.clone(), self.ct0.clone() + r.clone());
+        let b1:Aabb = Aabb::new(self.ct1.clone() - r.clone(), self.ct1.clone() + r);
+        Some(Aabb::surrounding(&b0, &b1))
+    }
 }
 
 impl Hittable for Hitlist {
@@ -86,36 +189,374 @@ impl Hittable for Hitlist {
         }
         return rec;
     }
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut bbox:Option<Aabb> = None;
+        for shape in &(self.shapes) {
+            let sbox:Aabb = shape.bounding_box()?;
+            bbox = match bbox {
+                Some(b) => Some(Aabb::surrounding(&b, &sbox)),
+                None => Some(sbox),
+            };
+        }
+        bbox
+    }
+    // one shape picked uniformly
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        if self.shapes.is_empty() { return 0.0; }
+        let sum:f64 = self.shapes.iter().map(|shape| shape.pdf_value(o, v)).sum();
+        sum / self.shapes.len() as f64
+    }
+    fn random(&self, o: &Vec3, smp: &mut dyn Sampler) -> Vec3 {
+        if self.shapes.is_empty() { return Vec3::new(1.0, 0.0, 0.0); }
+        let k:usize = (smp.randf(0.0, self.shapes.len() as f64) as usize).min(self.shapes.len() - 1);
+        self.shapes[k].random(o, smp)
+    }
 }
 
 impl <M: Material> Hittable for Sphere <M> {
     fn hit <'a> (&'a self, r: Ray, t_min: f64, t_max: f64) -> Option<Hitrec>{
-        let oc:Vec3 = r.origin() - self.ct();
-        let a:f64 = r.diraction().squared_length();
-        let h:f64 = (r.diraction()*oc.clone());
-        let c:f64 = (oc.squared_length()) - self.rad*self.rad;
-        let dis:f64 = h*h - a*c;
+        stats::test(Prim::Sphere);
+        hit_sphere(&self.ct, self.rad, &self.mat, r, t_min, t_max)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r:Vec3 = Vec3::new(self.rad.abs(), self.rad.abs(), self.rad.abs());
+        Some(Aabb::new(self.ct() - r.clone(), self.ct() + r))
+    }
+    // uniform over the cone the sphere fills, or every direction from inside it
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        let d2:f64 = (self.ct() - o.clone()).squared_length();
+        if d2 <= self.rad * self.rad { return 1.0 / (4.0 * PI); }
+        if self.hit(Ray::new(o.clone(), v.clone(), 0.0), 0.001, INF).is_none() { return 0.0; }
+        vec3::cone_pdf(vec3::cone_cos_max(self.rad, d2))
+    }
+    fn random(&self, o: &Vec3, smp: &mut dyn Sampler) -> Vec3 {
+        let dir:Vec3 = self.ct() - o.clone();
+        let d2:f64 = dir.squared_length();
+        if d2 <= self.rad * self.rad { return vec3::rand_uint_vec(smp); }
+        Onb::new(&dir).to_world(&vec3::rand_to_sphere(smp, self.rad, d2))
+    }
+}
+
+fn axis_vec(axis: usize, val: f64) -> Vec3 {
+    match axis {
+        0 => Vec3::new(val, 0.0, 0.0),
+        1 => Vec3::new(0.0, val, 0.0),
+        _ => Vec3::new(0.0, 0.0, val),
+    }
+}
+
+// hit with the ray o + t v, distance^2 / cos turns a density per unit area into one per solid angle
+fn area_pdf_value(shape: &dyn Hittable, o: &Vec3, v: &Vec3, pdf_area: f64) -> f64 {
+    match shape.hit(Ray::new(o.clone(), v.clone(), 0.0), 0.001, INF) {
+        Some(rec) => {
+            let d2:f64 = rec.t * rec.t * v.squared_length();
+            let cos:f64 = (v.clone() * rec.nf()).abs() / v.length();
+            if cos < 1e-12 { 0.0 } else { pdf_area * d2 / cos }
+        }
+        None => 0.0,
+    }
+}
+
+// toward a uniform point of the rectangle
+fn rect_random(axes: (usize, usize, usize), bounds: (f64, f64, f64, f64), k: f64, o: &Vec3, smp: &mut dyn Sampler) -> Vec3 {
+    let (a, b, c) = axes;
+    let (a0, a1, b0, b1) = bounds;
+    axis_vec(a, smp.randf(a0, a1)) + axis_vec(b, smp.randf(b0, b1)) + axis_vec(c, k) - o.clone()
+}
+
+// rectangle spanning [a0, a1] x [b0, b1] on the plane axis `c` = k, facing +c
+fn hit_rect<'a>(mat: &'a dyn Material, r: Ray, t_min: f64, t_max: f64, axes: (usize, usize, usize), bounds: (f64, f64, f64, f64), k: f64) -> Option<Hitrec<'a>> {
+    let (a, b, c) = axes;
+    let (a0, a1, b0, b1) = bounds;
+    let t:f64 = (k - Aabb::axis(&r.org, c)) / Aabb::axis(&r.dir, c);
+    if !(t > t_min && t < t_max) { return None; }
+    let pa:f64 = Aabb::axis(&r.org, a) + t * Aabb::axis(&r.dir, a);
+    let pb:f64 = Aabb::axis(&r.org, b) + t * Aabb::axis(&r.dir, b);
+    if pa < a0 || pa > a1 || pb < b0 || pb > b1 { return None; }
+    let mut rec:Hitrec = Hitrec::new(mat);
+    rec.t = t;
+    rec.u = (pa - a0) / (a1 - a0);
+    rec.v = (pb - b0) / (b1 - b0);
+    rec.p = r.at(t);
+    rec.set_face(r, axis_vec(c, 1.0));
+    Some(rec)
+}
+
+// the plane has no thickness, pad the box a little along its normal
+fn rect_box(axes: (usize, usize, usize), bounds: (f64, f64, f64, f64), k: f64) -> Aabb {
+    let (a, b, c) = axes;
+    let (a0, a1, b0, b1) = bounds;
+    let min:Vec3 = axis_vec(a, a0) + axis_vec(b, b0) + axis_vec(c, k - 0.0001);
+    let max:Vec3 = axis_vec(a, a1) + axis_vec(b, b1) + axis_vec(c, k + 0.0001);
+    Aabb::new(min, max)
+}
+
+#[derive(Debug)]
+pub struct XYRect <M: Material> {
+    pub x0: f64,
+    pub x1: f64,
+    pub y0: f64,
+    pub y1: f64,
+    pub k: f64,
+    pub mat: M,
+}
+
+impl <M: Material> XYRect <M> {
+    pub fn new(x0: f64, x1: f64, y0: f64, y1: f64, k: f64, mat: M) -> Self {
+        Self { x0, x1, y0, y1, k, mat }
+    }
+}
+
+impl <M: Material> Hittable for XYRect <M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<Hitrec> {
+        stats::test(Prim::Rect);
+        hit_rect(&self.mat, r, t_min, t_max, (0, 1, 2), (self.x0, self.x1, self.y0, self.y1), self.k)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(rect_box((0, 1, 2), (self.x0, self.x1, self.y0, self.y1), self.k))
+    }
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        area_pdf_value(self, o, v, 1.0 / ((self.x1 - self.x0) * (self.y1 - self.y0)))
+    }
+    fn random(&self, o: &Vec3, smp: &mut dyn Sampler) -> Vec3 {
+        rect_random((0, 1, 2), (self.x0, self.x1, self.y0, self.y1), self.k, o, smp)
+    }
+}
+
+#[derive(Debug)]
+pub struct XZRect <M: Material> {
+    pub x0: f64,
+    pub x1: f64,
+    pub z0: f64,
+    pub z1: f64,
+    pub k: f64,
+    pub mat: M,
+}
+
+impl <M: Material> XZRect <M> {
+    pub fn new(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, mat: M) -> Self {
+        Self { x0, x1, z0, z1, k, mat }
+    }
+}
+
+impl <M: Material> Hittable for XZRect <M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<Hitrec> {
+        stats::test(Prim::Rect);
+        hit_rect(&self.mat, r, t_min, t_max, (0, 2, 1), (self.x0, self.x1, self.z0, self.z1), self.k)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(rect_box((0, 2, 1), (self.x0, self.x1, self.z0, self.z1), self.k))
+    }
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        area_pdf_value(self, o, v, 1.0 / ((self.x1 - self.x0) * (self.z1 - self.z0)))
+    }
+    fn random(&self, o: &Vec3, smp: &mut dyn Sampler) -> Vec3 {
+        rect_random((0, 2, 1), (self.x0, self.x1, self.z0, self.z1), self.k, o, smp)
+    }
+}
+
+#[derive(Debug)]
+pub struct YZRect <M: Material> {
+    pub y0: f64,
+    pub y1: f64,
+    pub z0: f64,
+    pub z1: f64,
+    pub k: f64,
+    pub mat: M,
+}
+
+impl <M: Material> YZRect <M> {
+    pub fn new(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, mat: M) -> Self {
+        Self { y0, y1, z0, z1, k, mat }
+    }
+}
+
+impl <M: Material> Hittable for YZRect <M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<Hitrec> {
+        stats::test(Prim::Rect);
+        hit_rect(&self.mat, r, t_min, t_max, (1, 2, 0), (self.y0, self.y1, self.z0, self.z1), self.k)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(rect_box((1, 2, 0), (self.y0, self.y1, self.z0, self.z1), self.k))
+    }
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        area_pdf_value(self, o, v, 1.0 / ((self.y1 - self.y0) * (self.z1 - self.z0)))
+    }
+    fn random(&self, o: &Vec3, smp: &mut dyn Sampler) -> Vec3 {
+        rect_random((1, 2, 0), (self.y0, self.y1, self.z0, self.z1), self.k, o, smp)
+    }
+}
+
+// axis-aligned box made of six rectangles, normals pointing out
+#[derive(Debug)]
+pub struct Cuboid {
+    pub pmin: Vec3,
+    pub pmax: Vec3,
+    pub sides: Hitlist,
+}
+
+impl Cuboid {
+    pub fn new <M: Material + Clone + 'static> (p0: Vec3, p1: Vec3, mat: M) -> Self {
+        let pmin:Vec3 = Vec3::new(p0.x.min(p1.x), p0.y.min(p1.y), p0.z.min(p1.z));
+        let pmax:Vec3 = Vec3::new(p0.x.max(p1.x), p0.y.max(p1.y), p0.z.max(p1.z));
+        let (a, b) = (pmin.clone(), pmax.clone());
+        let mut sides:Hitlist = Hitlist::new();
+        sides.add(Arc::new(XYRect::new(a.x, b.x, a.y, b.y, b.z, mat.clone())));
+        sides.add(Arc::new(Flip::new(XYRect::new(a.x, b.x, a.y, b.y, a.z, mat.clone()))));
+        sides.add(Arc::new(XZRect::new(a.x, b.x, a.z, b.z, b.y, mat.clone())));
+        sides.add(Arc::new(Flip::new(XZRect::new(a.x, b.x, a.z, b.z, a.y, mat.clone()))));
+        sides.add(Arc::new(YZRect::new(a.y, b.y, a.z, b.z, b.x, mat.clone())));
+        sides.add(Arc::new(Flip::new(YZRect::new(a.y, b.y, a.z, b.z, a.x, mat))));
+        Self { pmin, pmax, sides }
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<Hitrec> {
+        self.sides.hit(r, t_min, t_max)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.pmin.clone(), self.pmax.clone()))
+    }
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        self.sides.pdf_value(o, v)
+    }
+    fn random(&self, o: &Vec3, smp: &mut dyn Sampler) -> Vec3 {
+        self.sides.random(o, smp)
+    }
+}
+
+// turns a shape inside out, front_face is reported for the other side
+#[derive(Debug)]
+pub struct Flip <H: Hittable> {
+    pub inner: H,
+}
+
+impl <H: Hittable> Flip <H> {
+    pub fn new(inner: H) -> Self { Self { inner } }
+}
+
+impl <H: Hittable> Hittable for Flip <H> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<Hitrec> {
+        let mut rec:Hitrec = self.inner.hit(r, t_min, t_max)?;
+        rec.front_face = !rec.front_face;
+        Some(rec)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.inner.bounding_box()
+    }
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        self.inner.pdf_value(o, v)
+    }
+    fn random(&self, o: &Vec3, smp: &mut dyn Sampler) -> Vec3 {
+        self.inner.random(o, smp)
+    }
+}
+
+// stamps the object and material ids of a scene shape on its hits, for the AOVs
+#[derive(Debug)]
+pub struct Tagged {
+    pub shape: Arc<dyn Hittable>,
+    pub obj: u32,
+    pub mtl: u32,
+}
+
+impl Tagged {
+    pub fn new(shape: Arc<dyn Hittable>, obj: u32, mtl: u32) -> Self { Self { shape, obj, mtl } }
+}
+
+impl Hittable for Tagged {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<Hitrec> {
+        let mut rec:Hitrec = self.shape.hit(r, t_min, t_max)?;
+        rec.obj = self.obj;
+        rec.mtl = self.mtl;
+        Some(rec)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.shape.bounding_box()
+    }
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        self.shape.pdf_value(o, v)
+    }
+    fn random(&self, o: &Vec3, smp: &mut dyn Sampler) -> Vec3 {
+        self.shape.random(o, smp)
+    }
+}
+
+// optional per-vertex normals and uvs are interpolated over the face
+#[derive(Debug)]
+pub struct Triangle <M: Material> {
+    pub p: [Vec3; 3],
+    pub n: Option<[Vec3; 3]>,
+    pub uv: Option<[(f64, f64); 3]>,
+    pub mat: M,
+}
+
+impl <M: Material> Triangle <M> {
+    pub fn new(p0: Vec3, p1: Vec3, p2: Vec3, mat: M) -> Self {
+        Self { p: [p0, p1, p2], n: None, uv: None, mat }
+    }
+    pub fn normal(&self) -> Vec3 {
+        Vec3::cross(self.p[1].clone() - self.p[0].clone(), self.p[2].clone() - self.p[0].clone()).unit()
+    }
+}
+
+impl <M: Material> Hittable for Triangle <M> {
+    // Moller-Trumbore, (b1, b2) are the barycentric weights of p1 and p2
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<Hitrec> {
+        stats::test(Prim::Triangle);
+        let e1:Vec3 = self.p[1].clone() - self.p[0].clone();
+        let e2:Vec3 = self.p[2].clone() - self.p[0].clone();
+        let pv:Vec3 = Vec3::cross(r.diraction(), e2.clone());
+        let det:f64 = e1.clone() * pv.clone();
+        if det.abs() < 1e-12 { return None; }
+        let inv:f64 = 1.0 / det;
+        let tv:Vec3 = r.origin() - self.p[0].clone();
+        let b1:f64 = (tv.clone() * pv) * inv;
+        if b1 < 0.0 || b1 > 1.0 { return None; }
+        let qv:Vec3 = Vec3::cross(tv, e1.clone());
+        let b2:f64 = (r.diraction() * qv.clone()) * inv;
+        if b2 < 0.0 || b1 + b2 > 1.0 { return None; }
+        let t:f64 = (e2.clone() * qv) * inv;
+        if !(t > t_min && t < t_max) { return None; }
+
+        let b0:f64 = 1.0 - b1 - b2;
         let mut rec:Hitrec = Hitrec::new(&(self.mat));
-        if dis <= 0.0 { return None; }
-        else {
-            let root:f64 = dis.sqrt();
-            let mut t:f64 = (-h - root) / a;
-            if (t > t_min && t < t_max) {
-                rec.t = t;
-                rec.p = r.at(t);
-                let nf:Vec3 = (rec.p() - self.ct()) / self.rad;
-                rec.set_face(r.clone(), nf);
-                return Some(rec); 
+        rec.t = t;
+        rec.p = r.at(t);
+        match &self.uv {
+            Some(uv) => {
+                rec.u = b0 * uv[0].0 + b1 * uv[1].0 + b2 * uv[2].0;
+                rec.v = b0 * uv[0].1 + b1 * uv[1].1 + b2 * uv[2].1;
+            }
+            None => {
+                rec.u = b1;
+                rec.v = b2;
             }
-            t = (-h + root) / a;
-            if (t > t_min && t < t_max) {
-                rec.t = t;
-                rec.p = r.at(t);
-                let nf:Vec3 = (rec.p() - self.ct()) / self.rad;
-                rec.set_face(r.clone(), nf);
-                return Some(rec); 
+        }
+        // the side is decided by the real face, the shading normal follows it
+        let ng:Vec3 = Vec3::cross(e1, e2).unit();
+        rec.set_face(r, ng);
+        // opposite vertex normals can cancel out, then the face normal stays
+        if let Some(n) = &self.n {
+            let ns:Vec3 = n[0].clone() * b0 + n[1].clone() * b1 + n[2].clone() * b2;
+            if ns.squared_length() > 1e-12 {
+                let ns:Vec3 = ns.unit();
+                rec.nf = if ns.clone() * rec.nf() < 0.0 { -ns } else { ns };
             }
-            return None;
         }
+        Some(rec)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        let p = &self.p;
+        let min:Vec3 = Vec3::new(p[0].x.min(p[1].x).min(p[2].x), p[0].y.min(p[1].y).min(p[2].y), p[0].z.min(p[1].z).min(p[2].z));
+        let max:Vec3 = Vec3::new(p[0].x.max(p[1].x).max(p[2].x), p[0].y.max(p[1].y).max(p[2].y), p[0].z.max(p[1].z).max(p[2].z));
+        Some(Aabb::new(min - 0.0001, max + 0.0001))
+    }
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        area_pdf_value(self, o, v, vec3::triangle_pdf(&self.p[0], &self.p[1], &self.p[2]))
+    }
+    fn random(&self, o: &Vec3, smp: &mut dyn Sampler) -> Vec3 {
+        vec3::rand_in_triangle(smp, &self.p[0], &self.p[1], &self.p[2]) - o.clone()
     }
 }
\ No newline at end of file
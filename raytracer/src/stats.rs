@@ -0,0 +1,131 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// primitives whose intersection tests are counted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Prim {
+    Sphere,
+    MovingSphere,
+    Rect,
+    Triangle,
+    Medium,
+}
+
+pub const PRIMS:[&str; 5] = ["sphere", "moving_sphere", "rect", "triangle", "medium"];
+
+// where the time of a render goes. Each worker counts the rays into its own
+// copy as it traces them. Intersection tests and bvh visits happen where
+// there is no copy at hand and sit in the innermost loop, so they are only
+// counted with profile(true), into a copy per thread handed back by take()
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Counters {
+    pub camera: u64,        // rays traced, by how they were made
+    pub specular: u64,
+    pub diffuse: u64,       // bounces drawn from the material's pdf
+    pub light: u64,         // bounces drawn toward a light by the mixture pdf;
+                            // there are no separate shadow rays
+    pub tests: [u64; 5],    // intersection tests, by Prim
+    pub bvh_visits: u64,
+    pub depth_limit: u64,   // paths cut off at the maximum depth
+    pub roulette: u64,      // paths ended by Russian roulette
+}
+
+impl Counters {
+    pub fn add(&mut self, o: &Counters) {
+        self.camera += o.camera;
+        self.specular += o.specular;
+        self.diffuse += o.diffuse;
+        self.light += o.light;
+        for (a, b) in self.tests.iter_mut().zip(o.tests.iter()) { *a += b; }
+        self.bvh_visits += o.bvh_visits;
+        self.depth_limit += o.depth_limit;
+        self.roulette += o.roulette;
+    }
+    pub fn rays(&self) -> u64 {
+        self.camera + self.specular + self.diffuse + self.light
+    }
+    // segments per camera path
+    pub fn path_length(&self) -> f64 {
+        if self.camera == 0 { return 0.0; }
+        self.rays() as f64 / self.camera as f64
+    }
+
+    pub fn summary(&self, seconds: f64) -> String {
+        let mut out:String = String::new();
+        out += &format!("rays            {} ({:.0} per second)\n", self.rays(), self.rays() as f64 / seconds.max(1e-9));
+        out += &format!("  camera        {}\n", self.camera);
+        out += &format!("  specular      {}\n", self.specular);
+        out += &format!("  diffuse       {}\n", self.diffuse);
+        out += &format!("  toward light  {}\n", self.light);
+        if profiling() {
+            out += "intersection tests\n";
+            for (name, n) in PRIMS.iter().zip(self.tests.iter()) {
+                out += &format!("  {:<14}{}\n", name, n);
+            }
+            out += &format!("bvh node visits {}\n", self.bvh_visits);
+        }
+        out += &format!("path length     {:.2}\n", self.path_length());
+        out += &format!("depth limit     {}\n", self.depth_limit);
+        out += &format!("roulette        {}\n", self.roulette);
+        out += &format!("time            {:.2}s\n", seconds);
+        out
+    }
+
+    pub fn json(&self, seconds: f64) -> String {
+        // null when they were not counted
+        let (tests, visits):(String, String) = if profiling() {
+            let tests:Vec<String> = PRIMS.iter().zip(self.tests.iter()).map(|(name, n)| format!("\"{}\": {}", name, n)).collect();
+            (format!("{{{}}}", tests.join(", ")), self.bvh_visits.to_string())
+        } else {
+            ("null".to_string(), "null".to_string())
+        };
+        format!(concat!(
+            "{{\n",
+            "  \"seconds\": {},\n",
+            "  \"rays\": {{\"camera\": {}, \"specular\": {}, \"diffuse\": {}, \"toward_light\": {}, \"total\": {}}},\n",
+            "  \"rays_per_second\": {},\n",
+            "  \"intersection_tests\": {},\n",
+            "  \"bvh_node_visits\": {},\n",
+            "  \"average_path_length\": {},\n",
+            "  \"depth_limit_terminations\": {},\n",
+            "  \"roulette_terminations\": {}\n",
+            "}}\n"),
+            seconds, self.camera, self.specular, self.diffuse, self.light, self.rays(),
+            self.rays() as f64 / seconds.max(1e-9), tests, visits, self.path_length(), self.depth_limit, self.roulette)
+    }
+}
+
+thread_local! {
+    static LOCAL: RefCell<Counters> = RefCell::new(Counters::default());
+}
+
+static PROFILE: AtomicBool = AtomicBool::new(false);
+
+// count intersection tests and bvh visits too, at some cost in speed
+pub fn profile(on: bool) {
+    PROFILE.store(on, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn profiling() -> bool {
+    PROFILE.load(Ordering::Relaxed)
+}
+
+pub fn bump(f: impl FnOnce(&mut Counters)) {
+    LOCAL.with(|c| f(&mut c.borrow_mut()));
+}
+
+#[inline]
+pub fn test(prim: Prim) {
+    if profiling() { bump(|c| c.tests[prim as usize] += 1); }
+}
+
+#[inline]
+pub fn visit() {
+    if profiling() { bump(|c| c.bvh_visits += 1); }
+}
+
+// this thread's counts so far, which start over from zero
+pub fn take() -> Counters {
+    LOCAL.with(|c| c.replace(Counters::default()))
+}
\ No newline at end of file
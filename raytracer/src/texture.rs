@@ -0,0 +1,107 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::color;
+use crate::color::Color;
+use crate::perlin::Perlin;
+use crate::tools::Rng;
+
+pub trait Texture: Debug + Send + Sync {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color;
+}
+
+#[derive(Debug, Clone)]
+pub struct SolidColor {
+    pub color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self { Self { color } }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color { self.color.clone() }
+}
+
+// 3D checker, cells are PI / scale wide
+#[derive(Debug, Clone)]
+pub struct Checker {
+    pub odd: Arc<dyn Texture>,
+    pub even: Arc<dyn Texture>,
+    pub scale: f64,
+}
+
+impl Checker {
+    pub fn new(odd: Arc<dyn Texture>, even: Arc<dyn Texture>, scale: f64) -> Self { Self { odd, even, scale } }
+    pub fn from_colors(c1: Color, c2: Color) -> Self {
+        Self::new(Arc::new(SolidColor::new(c1)), Arc::new(SolidColor::new(c2)), 10.0)
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        let sines:f64 = (self.scale * p.x).sin() * (self.scale * p.y).sin() * (self.scale * p.z).sin();
+        if sines < 0.0 { self.odd.value(u, v, p) } else { self.even.value(u, v, p) }
+    }
+}
+
+// (u, v) = (0, 0) is the bottom left of the image
+#[derive(Debug, Clone)]
+pub struct ImageTexture {
+    pub data: Vec<Color>,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl ImageTexture {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let img = image::open(path).map_err(|e| format!("cannot open `{}`: {}", path, e))?.to_rgb8();
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        // 8-bit images are sRGB encoded, the renderer works in linear light
+        let linear:Vec<f64> = (0..256).map(|k| color::srgb_decode(k as f64 / 255.0)).collect();
+        let data:Vec<Color> = img.pixels()
+            .map(|px| Color::new(linear[px[0] as usize], linear[px[1] as usize], linear[px[2] as usize]))
+            .collect();
+        Ok(Self { data, width, height })
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        if self.data.is_empty() { return Color::new(0.0, 1.0, 1.0); }
+        let u:f64 = u - u.floor();
+        let v:f64 = 1.0 - (v - v.floor());
+        let i:usize = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j:usize = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.data[j * self.width + i].clone()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoiseKind {
+    Noise,
+    Turbulence,
+    Marble,
+}
+
+#[derive(Debug, Clone)]
+pub struct NoiseTexture {
+    pub noise: Perlin,
+    pub scale: f64,
+    pub kind: NoiseKind,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64, kind: NoiseKind, rng: &mut Rng) -> Self { Self { noise: Perlin::new(rng), scale, kind } }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        let grey:f64 = match self.kind {
+            NoiseKind::Noise => 0.5 * (1.0 + self.noise.noise(&(p.clone() * self.scale))),
+            NoiseKind::Turbulence => self.noise.turb(&(p.clone() * self.scale), 7),
+            NoiseKind::Marble => 0.5 * (1.0 + (self.scale * p.z + 10.0 * self.noise.turb(p, 7)).sin()),
+        };
+        Color::new(1.0, 1.0, 1.0) * grey
+    }
+}
\ No newline at end of file
@@ -1,7 +1,3 @@
-extern crate rand;
-
-use rand::Rng;
-
 pub const INF:f64 = 9223372036854775807.0;
 pub const PI:f64 = 3.1415926535897932385;
 
@@ -9,13 +5,64 @@ pub fn dtr(degree: f64) -> f64 {
     degree * PI / 180.0
 }
 
-pub fn randf(low: f64, high: f64) -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(low, high)
+// mix a seed with a stream index (splitmix64 finalizer)
+pub fn mix_seed(seed: u64, idx: u64) -> u64 {
+    let mut z:u64 = seed ^ idx.wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// PCG32 (O'Neill, pcg-random.org); every (seed, stream) pair gives an
+// independent sequence, and all sampling goes through one of these
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+    inc: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng:Rng = Self { state: 0, inc: (stream << 1) | 1 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    // one sample of one pixel gets the same numbers whatever thread, tile or
+    // pass renders it
+    pub fn for_sample(seed: u64, pixel: u64, sample: u64) -> Self {
+        Self::new(mix_seed(mix_seed(seed, pixel), sample), pixel)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old:u64 = self.state;
+        self.state = old.wrapping_mul(6364136223846793005).wrapping_add(self.inc);
+        let xorshifted:u32 = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    // 53 random bits in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        let hi:u64 = self.next_u32() as u64;
+        let lo:u64 = self.next_u32() as u64;
+        ((hi << 21) | (lo >> 11)) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn randf(&mut self, low: f64, high: f64) -> f64 {
+        low + (high - low) * self.next_f64()
+    }
 }
 
 pub fn clamp(x: f64, x_min: f64, x_max: f64) -> f64 {
     if x < x_min { return x_min; }
     if x > x_max { return x_max; }
     return x;
-}
\ No newline at end of file
+}
+
+// a file in the temp directory for a test to write, apart per process
+#[cfg(test)]
+pub fn temp_path(name: &str) -> String {
+    std::env::temp_dir().join(format!("raytracer-{}-{}", std::process::id(), name)).to_string_lossy().to_string()
+}
@@ -0,0 +1,251 @@
+use std::ops::Mul;
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::aabb::Aabb;
+use crate::shapes::Hitrec;
+use crate::shapes::Hittable;
+use crate::tools;
+use crate::sampler::Sampler;
+
+// row-major 4x4 matrix acting on column vectors
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn identity() -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (k, row) in m.iter_mut().enumerate() { row[k] = 1.0; }
+        Self { m }
+    }
+
+    pub fn translate(offset: Vec3) -> Self {
+        let mut t:Mat4 = Mat4::identity();
+        t.m[0][3] = offset.x;
+        t.m[1][3] = offset.y;
+        t.m[2][3] = offset.z;
+        t
+    }
+
+    pub fn scale(s: Vec3) -> Self {
+        let mut t:Mat4 = Mat4::identity();
+        t.m[0][0] = s.x;
+        t.m[1][1] = s.y;
+        t.m[2][2] = s.z;
+        t
+    }
+
+    // counter-clockwise looking down the axis, angle in degrees
+    pub fn rotate(axis: Vec3, degree: f64) -> Self {
+        let a:Vec3 = axis.unit();
+        let theta:f64 = tools::dtr(degree);
+        let (sin, cos) = (theta.sin(), theta.cos());
+        let k:f64 = 1.0 - cos;
+        let mut t:Mat4 = Mat4::identity();
+        t.m[0][0] = cos + a.x * a.x * k;
+        t.m[0][1] = a.x * a.y * k - a.z * sin;
+        t.m[0][2] = a.x * a.z * k + a.y * sin;
+        t.m[1][0] = a.y * a.x * k + a.z * sin;
+        t.m[1][1] = cos + a.y * a.y * k;
+        t.m[1][2] = a.y * a.z * k - a.x * sin;
+        t.m[2][0] = a.z * a.x * k - a.y * sin;
+        t.m[2][1] = a.z * a.y * k + a.x * sin;
+        t.m[2][2] = cos + a.z * a.z * k;
+        t
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut t:Mat4 = Mat4::identity();
+        for r in 0..4 {
+            for c in 0..4 { t.m[r][c] = self.m[c][r]; }
+        }
+        t
+    }
+
+    // of the upper left 3x3, how much the matrix scales volumes
+    pub fn det3(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // Gauss-Jordan with partial pivoting, None if singular
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv:Mat4 = Mat4::identity();
+        for c in 0..4 {
+            let mut piv:usize = c;
+            for r in c + 1..4 {
+                if a[r][c].abs() > a[piv][c].abs() { piv = r; }
+            }
+            if a[piv][c].abs() < 1e-12 { return None; }
+            a.swap(c, piv);
+            inv.m.swap(c, piv);
+            let d:f64 = a[c][c];
+            for k in 0..4 {
+                a[c][k] /= d;
+                inv.m[c][k] /= d;
+            }
+            for r in 0..4 {
+                if r == c { continue; }
+                let f:f64 = a[r][c];
+                if f == 0.0 { continue; }
+                for k in 0..4 {
+                    a[r][k] -= f * a[c][k];
+                    inv.m[r][k] -= f * inv.m[c][k];
+                }
+            }
+        }
+        Some(inv)
+    }
+
+    pub fn point(&self, p: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        )
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut t = [[0.0; 4]; 4];
+        for (r, row) in t.iter_mut().enumerate() {
+            for (c, val) in row.iter_mut().enumerate() {
+                *val = (0..4).map(|k| self.m[r][k] * other.m[k][c]).sum();
+            }
+        }
+        Self { m: t }
+    }
+}
+
+// places any shape in the world through an affine matrix
+#[derive(Debug)]
+pub struct Transform {
+    pub inner: Arc<dyn Hittable>,
+    pub m: Mat4,
+    pub inv: Mat4,
+    pub inv_t: Mat4,    // normals go through the inverse transpose
+    pub det: f64,
+    pub bbox: Option<Aabb>,
+}
+
+impl Transform {
+    // panics on a singular matrix (e.g. a zero scale)
+    pub fn new(inner: Arc<dyn Hittable>, m: Mat4) -> Self {
+        let inv:Mat4 = m.inverse().expect("transform matrix is not invertible");
+        let inv_t:Mat4 = inv.transpose();
+        let bbox:Option<Aabb> = inner.bounding_box().map(|b| {
+            let mut out:Option<Aabb> = None;
+            for k in 0..8 {
+                let corner:Vec3 = Vec3::new(
+                    if k & 1 == 0 { b.min.x } else { b.max.x },
+                    if k & 2 == 0 { b.min.y } else { b.max.y },
+                    if k & 4 == 0 { b.min.z } else { b.max.z },
+                );
+                let p:Vec3 = m.point(&corner);
+                let pbox:Aabb = Aabb::new(p.clone(), p);
+                out = Some(match out {
+                    Some(o) => Aabb::surrounding(&o, &pbox),
+                    None => pbox,
+                });
+            }
+            out.unwrap()
+        });
+        let det:f64 = m.det3().abs();
+        Self { inner, m, inv, inv_t, det, bbox }
+    }
+    pub fn translate(inner: Arc<dyn Hittable>, offset: Vec3) -> Self {
+        Self::new(inner, Mat4::translate(offset))
+    }
+    pub fn rotate(inner: Arc<dyn Hittable>, axis: Vec3, degree: f64) -> Self {
+        Self::new(inner, Mat4::rotate(axis, degree))
+    }
+    pub fn scale(inner: Arc<dyn Hittable>, s: Vec3) -> Self {
+        Self::new(inner, Mat4::scale(s))
+    }
+}
+
+impl Hittable for Transform {
+    // the local direction is not normalized, so t is the same in both spaces
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<Hitrec> {
+        let mut local:Ray = Ray::new(self.inv.point(&r.org), self.inv.vector(&r.dir), r.time());
+        local.fog = r.fog;
+        let mut rec:Hitrec = self.inner.hit(local, t_min, t_max)?;
+        rec.p = self.m.point(&rec.p);
+        rec.nf = self.inv_t.vector(&rec.nf).unit();
+        Some(rec)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox.clone()
+    }
+    // the matrix maps directions w' to w = Mw' / |Mw'|, which stretches solid
+    // angles by |det M| / |Mw'|^3; only a non-uniform scale makes that differ from 1
+    fn pdf_value(&self, o: &Vec3, v: &Vec3) -> f64 {
+        let local:Vec3 = self.inv.vector(v);
+        let stretch:f64 = v.length() / local.length();
+        self.inner.pdf_value(&self.inv.point(o), &local) * stretch * stretch * stretch / self.det
+    }
+    fn random(&self, o: &Vec3, smp: &mut dyn Sampler) -> Vec3 {
+        self.m.vector(&self.inner.random(&self.inv.point(o), smp))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3;
+    use crate::color::Color;
+    use crate::material::DiffuseLight;
+    use crate::sampler::Independent;
+    use crate::shapes::Sphere;
+
+    const N:usize = 200_000;
+
+    // a sphere light squashed into an ellipsoid, seen from outside
+    #[test]
+    fn scaled_light_density_is_normalized() {
+        let ball:Arc<dyn Hittable> = Arc::new(Sphere::new(Vec3::zero(), 1.0, DiffuseLight::new(Color::new(1.0, 1.0, 1.0))));
+        let m:Mat4 = Mat4::translate(Vec3::new(0.5, 1.0, -4.0)) * Mat4::rotate(Vec3::new(1.0, 1.0, 0.0), 30.0) * Mat4::scale(Vec3::new(3.0, 1.0, 0.4));
+        let light:Transform = Transform::new(ball, m);
+        let o:Vec3 = Vec3::zero();
+        let mut smp:Independent = Independent::new(9);
+
+        // the density integrates to 1 over the sphere of directions, and the
+        // solid angle it covers is what the sampled directions say it is
+        let (mut total, mut seen):(f64, usize) = (0.0, 0);
+        for _ in 0..N {
+            let v:Vec3 = vec3::rand_uint_vec(&mut smp);
+            total += light.pdf_value(&o, &v);
+            if light.hit(Ray::new(o.clone(), v, 0.0), 0.001, tools::INF).is_some() { seen += 1; }
+        }
+        let integral:f64 = total * 4.0 * tools::PI / N as f64;
+        assert!((integral - 1.0).abs() < 0.02, "integral {}", integral);
+        let mut inverse:f64 = 0.0;
+        for _ in 0..N {
+            let v:Vec3 = light.random(&o, &mut smp);
+            let p:f64 = light.pdf_value(&o, &v);
+            assert!(p > 0.0);
+            inverse += 1.0 / p;
+        }
+        let solid:f64 = seen as f64 * 4.0 * tools::PI / N as f64;
+        assert!((inverse / N as f64 / solid - 1.0).abs() < 0.02, "{} against {}", inverse / N as f64, solid);
+    }
+}
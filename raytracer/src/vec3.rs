@@ -1,5 +1,6 @@
 use std::ops::{Add, AddAssign, Sub, SubAssign, Mul, MulAssign, Div, Neg};
-use crate::tools::randf;
+use crate::tools::Rng;
+use crate::sampler::Sampler;
 use crate::tools::PI;
 
 #[derive(Clone, Debug, PartialEq)]
@@ -65,12 +66,12 @@ impl Vec3 {
         }
     }
 
-    pub fn randv() -> Self {
-        Self::new(randf(0.0, 1.0), randf(0.0, 1.0), randf(0.0, 1.0))
+    pub fn randv(rng: &mut Rng) -> Self {
+        Self::new(rng.randf(0.0, 1.0), rng.randf(0.0, 1.0), rng.randf(0.0, 1.0))
     }
 
-    pub fn randvr(x_min: f64, x_max: f64) -> Self {
-        Self::new(randf(x_min, x_max), randf(x_min, x_max), randf(x_min, x_max))
+    pub fn randvr(rng: &mut Rng, x_min: f64, x_max: f64) -> Self {
+        Self::new(rng.randf(x_min, x_max), rng.randf(x_min, x_max), rng.randf(x_min, x_max))
     }
 
     pub fn reflect(v: Self, n: Self) -> Self {
@@ -226,22 +227,23 @@ impl Neg for Vec3 {
     }
 }
 
-pub fn rand_in_unit_sphere() -> Vec3 {
+pub fn rand_in_unit_sphere(smp: &mut dyn Sampler) -> Vec3 {
     loop {
-        let p:Vec3 = Vec3::randvr(-1.0, 1.0);
+        let p:Vec3 = Vec3::new(smp.randf(-1.0, 1.0), smp.randf(-1.0, 1.0), smp.randf(-1.0, 1.0));
         if p.squared_length() < 1.0 { return p };
     }
 }
 
-pub fn rand_uint_vec() -> Vec3 {
-    let a:f64 = randf(0.0, 2.0*PI);
-    let z:f64 = randf(-1.0, 1.0);
+pub fn rand_uint_vec(smp: &mut dyn Sampler) -> Vec3 {
+    let (r1, r2) = smp.get2d();
+    let a:f64 = 2.0 * PI * r1;
+    let z:f64 = 2.0 * r2 - 1.0;
     let r:f64 = (1.0 - z*z).sqrt();
     Vec3::new(r * a.cos(), r * a.sin(), z)
 }
 
-pub fn rand_in_hemisphere(nf: Vec3) -> Vec3 {
-    let unit_sphere:Vec3 = rand_in_unit_sphere();
+pub fn rand_in_hemisphere(smp: &mut dyn Sampler, nf: Vec3) -> Vec3 {
+    let unit_sphere:Vec3 = rand_in_unit_sphere(smp);
     if (unit_sphere.clone()*nf.clone()) > 0.0 {
         unit_sphere.clone()
     }else{
@@ -249,13 +251,109 @@ pub fn rand_in_hemisphere(nf: Vec3) -> Vec3 {
     }
 }
 
-pub fn rand_in_unit_disk() -> Vec3 {
+pub fn rand_in_unit_disk(smp: &mut dyn Sampler) -> Vec3 {
     loop {
-        let p:Vec3 = Vec3::new(randf(-1.0, 1.0), randf(-1.0, 1.0), 0.0);
+        let p:Vec3 = Vec3::new(smp.randf(-1.0, 1.0), smp.randf(-1.0, 1.0), 0.0);
         if p.squared_length() < 1.0 { return p; }
     }
 }
 
+// orthonormal frame (u, v, w) with w along a given normal
+#[derive(Clone, Debug, PartialEq)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    // Duff et al., "Building an Orthonormal Basis, Revisited": no branch on the
+    // axis and stable down to n = (0, 0, -1)
+    pub fn new(n: &Vec3) -> Self {
+        let w:Vec3 = n.unit();
+        let sign:f64 = 1.0_f64.copysign(w.z);
+        let a:f64 = -1.0 / (sign + w.z);
+        let b:f64 = w.x * w.y * a;
+        let u:Vec3 = Vec3::new(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x);
+        let v:Vec3 = Vec3::new(b, sign + w.y * w.y * a, -w.y);
+        Self { u, v, w }
+    }
+    pub fn local(&self, a: f64, b: f64, c: f64) -> Vec3 {
+        self.u.clone() * a + self.v.clone() * b + self.w.clone() * c
+    }
+    pub fn to_world(&self, a: &Vec3) -> Vec3 { self.local(a.x, a.y, a.z) }
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(a.clone() * self.u.clone(), a.clone() * self.v.clone(), a.clone() * self.w.clone())
+    }
+}
+
+// The samplers below work in a local frame around +z (see Onb); each comes
+// with the density of what it returns.
+
+// pdf cos(theta) / pi over the upper hemisphere
+pub fn rand_cosine_direction(smp: &mut dyn Sampler) -> Vec3 {
+    let (r1, r2) = smp.get2d();
+    let phi:f64 = 2.0 * PI * r1;
+    Vec3::new(phi.cos() * r2.sqrt(), phi.sin() * r2.sqrt(), (1.0 - r2).sqrt())
+}
+
+pub fn cosine_pdf(cos_theta: f64) -> f64 {
+    if cos_theta > 0.0 { cos_theta / PI } else { 0.0 }
+}
+
+// uniform over the cone a sphere of `radius` fills when its center is at
+// squared distance `d2` along +z; d2 must exceed radius^2
+pub fn rand_to_sphere(smp: &mut dyn Sampler, radius: f64, d2: f64) -> Vec3 {
+    let cos_max:f64 = cone_cos_max(radius, d2);
+    let (r1, r2) = smp.get2d();
+    let z:f64 = 1.0 + r1 * (cos_max - 1.0);
+    let phi:f64 = 2.0 * PI * r2;
+    let s:f64 = (1.0 - z * z).max(0.0).sqrt();
+    Vec3::new(phi.cos() * s, phi.sin() * s, z)
+}
+
+pub fn cone_cos_max(radius: f64, d2: f64) -> f64 {
+    (1.0 - radius * radius / d2).max(0.0).sqrt()
+}
+
+// per unit solid angle, inside the cone
+pub fn cone_pdf(cos_max: f64) -> f64 {
+    1.0 / (2.0 * PI * (1.0 - cos_max))
+}
+
+// uniform point of a triangle, in world space
+pub fn rand_in_triangle(smp: &mut dyn Sampler, p0: &Vec3, p1: &Vec3, p2: &Vec3) -> Vec3 {
+    let (r1, r2) = smp.get2d();
+    let s:f64 = r1.sqrt();
+    p0.clone() * (1.0 - s) + p1.clone() * (s * (1.0 - r2)) + p2.clone() * (s * r2)
+}
+
+// per unit area
+pub fn triangle_pdf(p0: &Vec3, p1: &Vec3, p2: &Vec3) -> f64 {
+    let area:f64 = 0.5 * Vec3::cross(p1.clone() - p0.clone(), p2.clone() - p0.clone()).length();
+    1.0 / area
+}
+
+// uniform point of the unit disk in the xy plane; Shirley and Chiu's concentric
+// map keeps neighbouring inputs together, unlike rand_in_unit_disk
+pub fn rand_concentric_disk(smp: &mut dyn Sampler) -> Vec3 {
+    let (r1, r2) = smp.get2d();
+    let a:f64 = 2.0 * r1 - 1.0;
+    let b:f64 = 2.0 * r2 - 1.0;
+    if a == 0.0 && b == 0.0 { return Vec3::zero(); }
+    let (r, phi) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    Vec3::new(r * phi.cos(), r * phi.sin(), 0.0)
+}
+
+// per unit area
+pub fn concentric_disk_pdf() -> f64 {
+    1.0 / PI
+}
+
 // #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -393,3 +491,138 @@ pub mod tests {
         Vec3::new(0.0, 0.0, 0.0).unit();
     }
 }
+
+#[cfg(test)]
+mod sampling_tests {
+    use super::*;
+    use crate::sampler::Independent;
+
+    const N:usize = 100_000;
+
+    // Pearson's statistic of `counts` against bin probabilities `probs`
+    fn chi2(counts: &[usize], probs: &[f64]) -> f64 {
+        let total:f64 = counts.iter().sum::<usize>() as f64;
+        counts.iter().zip(probs).map(|(&c, &p)| {
+            let e:f64 = total * p;
+            (c as f64 - e) * (c as f64 - e) / e
+        }).sum()
+    }
+
+    // chi-square with 9 degrees of freedom stays under 27.9 with probability 0.999
+    const CHI2_9:f64 = 27.9;
+
+    fn near(a: f64, b: f64, eps: f64) -> bool { (a - b).abs() < eps }
+
+    #[test]
+    fn onb_is_orthonormal() {
+        let mut rng:Independent = Independent::new(1);
+        let mut normals:Vec<Vec3> = vec![
+            Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(1e-9, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, -3.0, 0.0),
+        ];
+        for _ in 0..1000 { normals.push(rand_uint_vec(&mut rng)); }
+        for n in normals {
+            let b:Onb = Onb::new(&n);
+            assert!(near(b.u.length(), 1.0, 1e-9) && near(b.v.length(), 1.0, 1e-9) && near(b.w.length(), 1.0, 1e-9));
+            assert!(near(b.u.clone() * b.v.clone(), 0.0, 1e-9));
+            assert!(near(b.u.clone() * b.w.clone(), 0.0, 1e-9));
+            assert!(near(b.v.clone() * b.w.clone(), 0.0, 1e-9));
+            assert!((Vec3::cross(b.u.clone(), b.v.clone()) - b.w.clone()).length() < 1e-9);
+            assert!((b.w.clone() - n.unit()).length() < 1e-12);
+            let a:Vec3 = Vec3::new(0.3, -2.0, 5.0);
+            assert!((b.to_local(&b.to_world(&a)) - a).length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn cosine_direction_follows_its_pdf() {
+        let mut rng:Independent = Independent::new(2);
+        let mut counts:Vec<usize> = vec![0; 10];
+        let mut est:f64 = 0.0;
+        for _ in 0..N {
+            let d:Vec3 = rand_cosine_direction(&mut rng);
+            assert!(near(d.length(), 1.0, 1e-9) && d.z >= 0.0);
+            counts[((d.z * 10.0) as usize).min(9)] += 1;
+            est += d.z * d.z / cosine_pdf(d.z);
+        }
+        // P(z < c) = c^2
+        let probs:Vec<f64> = (0..10).map(|k| ((k + 1) * (k + 1) - k * k) as f64 / 100.0).collect();
+        assert!(chi2(&counts, &probs) < CHI2_9);
+        // the integral of cos^2 over the hemisphere is 2 pi / 3
+        assert!(near(est / N as f64, 2.0 * PI / 3.0, 0.01));
+    }
+
+    #[test]
+    fn sphere_cone_is_uniform() {
+        let mut rng:Independent = Independent::new(3);
+        let (radius, d2):(f64, f64) = (1.0, 9.0);
+        let cos_max:f64 = cone_cos_max(radius, d2);
+        let mut counts:Vec<usize> = vec![0; 10];
+        let (mut sx, mut sy):(f64, f64) = (0.0, 0.0);
+        for _ in 0..N {
+            let d:Vec3 = rand_to_sphere(&mut rng, radius, d2);
+            assert!(near(d.length(), 1.0, 1e-9) && d.z >= cos_max - 1e-12);
+            // every direction hits the sphere centered at (0, 0, 3)
+            let h:f64 = d.z * d2.sqrt();
+            assert!(d2 - h * h <= radius * radius + 1e-9);
+            counts[(((d.z - cos_max) / (1.0 - cos_max) * 10.0) as usize).min(9)] += 1;
+            sx += d.x;
+            sy += d.y;
+        }
+        // uniform in solid angle means uniform in z
+        assert!(chi2(&counts, &[0.1; 10]) < CHI2_9);
+        assert!(near(sx / N as f64, 0.0, 0.005) && near(sy / N as f64, 0.0, 0.005));
+        let solid:f64 = 2.0 * PI * (1.0 - cos_max);
+        assert!(near(cone_pdf(cos_max) * solid, 1.0, 1e-12));
+    }
+
+    #[test]
+    fn triangle_points_are_uniform() {
+        let mut rng:Independent = Independent::new(4);
+        let (p0, p1, p2) = (Vec3::new(0.0, 0.0, 0.0), Vec3::new(4.0, 0.0, 1.0), Vec3::new(1.0, 3.0, 0.0));
+        let e1:Vec3 = p1.clone() - p0.clone();
+        let e2:Vec3 = p2.clone() - p0.clone();
+        // barycentrics by least squares on the two edges
+        let (d11, d12, d22) = (e1.clone() * e1.clone(), e1.clone() * e2.clone(), e2.clone() * e2.clone());
+        let det:f64 = d11 * d22 - d12 * d12;
+        let mut counts:Vec<usize> = vec![0; 4];
+        let mut mean:Vec3 = Vec3::zero();
+        for _ in 0..N {
+            let p:Vec3 = rand_in_triangle(&mut rng, &p0, &p1, &p2);
+            let q:Vec3 = p.clone() - p0.clone();
+            let (q1, q2) = (q.clone() * e1.clone(), q * e2.clone());
+            let b1:f64 = (d22 * q1 - d12 * q2) / det;
+            let b2:f64 = (d11 * q2 - d12 * q1) / det;
+            let b0:f64 = 1.0 - b1 - b2;
+            assert!(b0 >= -1e-9 && b1 >= -1e-9 && b2 >= -1e-9);
+            // the midpoints cut it into four triangles of equal area
+            let k:usize = if b0 > 0.5 { 0 } else if b1 > 0.5 { 1 } else if b2 > 0.5 { 2 } else { 3 };
+            counts[k] += 1;
+            mean += p;
+        }
+        // 3 degrees of freedom, p = 0.001
+        assert!(chi2(&counts, &[0.25; 4]) < 16.3);
+        let centroid:Vec3 = (p0.clone() + p1.clone() + p2.clone()) / 3.0;
+        assert!((mean / N as f64 - centroid).length() < 0.02);
+        assert!(near(triangle_pdf(&p0, &p1, &p2), 1.0 / (0.5 * Vec3::cross(e1, e2).length()), 1e-12));
+    }
+
+    #[test]
+    fn concentric_disk_is_uniform() {
+        let mut rng:Independent = Independent::new(5);
+        let mut rings:Vec<usize> = vec![0; 10];
+        let mut sectors:Vec<usize> = vec![0; 10];
+        for _ in 0..N {
+            let p:Vec3 = rand_concentric_disk(&mut rng);
+            let r:f64 = p.length();
+            assert!(r <= 1.0 + 1e-12 && p.z == 0.0);
+            // P(r < a) = a^2, so r^2 is uniform
+            rings[((r * r * 10.0) as usize).min(9)] += 1;
+            let phi:f64 = p.y.atan2(p.x) + PI;
+            sectors[((phi / (2.0 * PI) * 10.0) as usize).min(9)] += 1;
+        }
+        assert!(chi2(&rings, &[0.1; 10]) < CHI2_9);
+        assert!(chi2(&sectors, &[0.1; 10]) < CHI2_9);
+        assert!(near(concentric_disk_pdf() * PI, 1.0, 1e-12));
+    }
+}
\ No newline at end of file